@@ -7,13 +7,13 @@
     LD D, 0x88
 
 ; Test BIT instruction
-    BIT 1, A    ; Test bit 1 of A (should set Z flag)
-    BIT 6, B    ; Test bit 6 of B (should not set Z flag)
+    BIT 1, A    ; Test bit 1 of A (set, resets the Z flag)
+    BIT 6, B    ; Test bit 6 of B (clear, sets the Z flag)
 
 ; Test conditional jump
     JP NZ, SetBitInC
 
-; If Z flag is not set, we should not reach this point
+; Z is set by BIT 6, B so the jump is not taken and the program ends here
     XOR A       ; Reset A to 0
     HALT
 
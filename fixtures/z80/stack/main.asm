@@ -11,7 +11,7 @@ CALL 0x000B   ; CD 0B 00
 ; Halt the program
 HALT          ; 76
 
-; Subroutine (0x0010):
+; Subroutine (0x000B):
 ; Add A and B, store the result at (DE)
 ; Preserve registers using PUSH and POP
 
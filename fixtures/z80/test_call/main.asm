@@ -1,6 +1,6 @@
 org 0x0000
 
-CALL 0x0010 ; CD 10 00
+CALL 0x0004 ; CD 04 00
 
 HALT ; 76
 
@@ -0,0 +1,88 @@
+// audio.rs
+use std::{cell::RefCell, rc::Rc};
+
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use tracing::warn;
+
+use super::sound::{SoundSource, SAMPLE_RATE};
+
+const T_STATES_PER_SAMPLE: f64 = 3_579_545.0 / SAMPLE_RATE as f64;
+
+// Samples are queued in blocks of this size
+const BUFFER_SIZE: usize = 1024;
+
+// Stop queueing when SDL is this far behind, the emulation is not throttled
+// and would otherwise build up seconds of latency
+const MAX_QUEUED_BYTES: u32 = (SAMPLE_RATE / 5) * 4;
+
+// Mixes every sound chip into a mono stream played by SDL
+pub struct Audio {
+    queue: Option<AudioQueue<f32>>,
+    sources: Vec<Rc<RefCell<dyn SoundSource>>>,
+    buffer: Vec<f32>,
+    t_states: f64,
+}
+
+impl Audio {
+    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE as i32),
+            channels: Some(1),
+            samples: Some(BUFFER_SIZE as u16),
+        };
+
+        // Keep running without sound if there's no audio device
+        let queue = match sdl_context
+            .audio()
+            .and_then(|audio| audio.open_queue::<f32, _>(None, &desired))
+        {
+            Ok(queue) => {
+                queue.resume();
+                Some(queue)
+            }
+            Err(err) => {
+                warn!("Failed to open the audio device: {}", err);
+                None
+            }
+        };
+
+        Audio {
+            queue,
+            sources: Vec::new(),
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            t_states: 0.0,
+        }
+    }
+
+    pub fn add_source(&mut self, source: Rc<RefCell<dyn SoundSource>>) {
+        self.sources.push(source);
+    }
+
+    // Generates the samples for the time the CPU just ran
+    pub fn run(&mut self, t_states: u32) {
+        let Some(queue) = &self.queue else {
+            return;
+        };
+
+        self.t_states += t_states as f64;
+        while self.t_states >= T_STATES_PER_SAMPLE {
+            self.t_states -= T_STATES_PER_SAMPLE;
+
+            let sample: f32 = self
+                .sources
+                .iter()
+                .map(|source| source.borrow_mut().generate_sample())
+                .sum();
+            self.buffer.push(sample.clamp(-1.0, 1.0));
+
+            if self.buffer.len() == BUFFER_SIZE {
+                if queue.size() < MAX_QUEUED_BYTES {
+                    if let Err(err) = queue.queue_audio(&self.buffer) {
+                        warn!("Failed to queue audio: {}", err);
+                    }
+                }
+                self.buffer.clear();
+            }
+        }
+    }
+}
@@ -1,51 +1,105 @@
-use super::IoDevice;
+use std::{cell::RefCell, rc::Rc};
 
-struct Bus {
-    slot_count: u8,
-    slots: Vec<Box<dyn IoDevice>>,
+use anyhow::{bail, Result};
+use tracing::warn;
 
-    vdp_io_clock: u8,
-    primary_slot_config: u8,
-    slot3_secondary_config: u8,
+use super::{memory::Memory, IoDevice};
+
+// What answers to a port on the bus: the device and the port it sees, which
+// differs from the bus port on mirrors
+struct IoPort {
+    device: Rc<RefCell<dyn IoDevice>>,
+    port: u8,
 }
 
-impl Bus {
-    pub fn new() -> Self {
-        let slot_count = 4;
+// Connects the Z80 to the MSX memory and I/O devices
+pub struct Bus {
+    pub memory: Memory,
+    // Resolved when devices are registered, so IN and OUT are a lookup
+    ports: [Option<IoPort>; 256],
 
-        // Create a Vec<Box<dyn IoDevice>> with EmptySlot instances using a loop
-        let mut slots: Vec<Box<dyn IoDevice>> = Vec::with_capacity(slot_count as usize);
-        for _ in 0..slot_count {
-            slots.push(Box::new(EmptySlot));
-        }
+    // debug options
+    pub log_unmapped_writes: bool,
+}
 
+impl Bus {
+    pub fn new(memory: Memory) -> Self {
         Self {
-            slot_count,
-            slots,
-
-            vdp_io_clock: 0,
-            primary_slot_config: 0,
-            slot3_secondary_config: 0,
+            memory,
+            ports: std::array::from_fn(|_| None),
+            log_unmapped_writes: false,
         }
     }
 
-    fn reset(&mut self) {
-        self.vdp_io_clock = 0;
-        self.primary_slot_config = 0;
+    pub fn register_device(&mut self, device: Rc<RefCell<dyn IoDevice>>) -> Result<()> {
+        self.register_mirrored_device(device, 0x00)
+    }
+
+    // Registers a device that ignores the `mirror_mask` bits of the port,
+    // like the MSX1 VDP that answers to all of 0x98-0x9F
+    pub fn register_mirrored_device(
+        &mut self,
+        device: Rc<RefCell<dyn IoDevice>>,
+        mirror_mask: u8,
+    ) -> Result<()> {
+        let claimed: Vec<(u8, u8)> = {
+            let device = device.borrow();
+            (0..=0xFF)
+                .map(|port| (port, port & !mirror_mask))
+                .filter(|(_, decoded)| device.is_valid_port(*decoded))
+                .collect()
+        };
+
+        if let Some((port, _)) = claimed
+            .iter()
+            .find(|(port, _)| self.ports[*port as usize].is_some())
+        {
+            bail!("I/O port {:#04X} is claimed by more than one device", port);
+        }
+
+        for (port, decoded) in claimed {
+            self.ports[port as usize] = Some(IoPort {
+                device: device.clone(),
+                port: decoded,
+            });
+        }
+
+        Ok(())
     }
 }
 
-#[derive(Clone)]
-struct EmptySlot;
+impl z80::Bus for Bus {
+    fn read(&mut self, address: u16) -> u8 {
+        self.memory.read_byte(address)
+    }
 
-impl IoDevice for EmptySlot {
-    fn is_valid_port(&self, _port: u8) -> bool {
-        false
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory.write_byte(address, value);
     }
 
-    fn read(&mut self, _port: u8) -> u8 {
-        0xFF
+    // The MSX only decodes the low 8 bits of the I/O address, nothing
+    // drives the data bus on unmapped ports so it reads 0xFF
+    fn input(&mut self, port: u16) -> u8 {
+        match &self.ports[port as u8 as usize] {
+            Some(io_port) => io_port.device.borrow_mut().read(io_port.port),
+            None => 0xFF,
+        }
     }
 
-    fn write(&mut self, _port: u8, _data: u8) {}
+    fn output(&mut self, port: u16, value: u8) {
+        match &self.ports[port as u8 as usize] {
+            Some(io_port) => io_port.device.borrow_mut().write(io_port.port, value),
+            None if self.log_unmapped_writes => {
+                warn!(
+                    "Write {:02X} to unmapped I/O port {:02X}",
+                    value, port as u8
+                );
+            }
+            None => {}
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.memory.peek(address)
+    }
 }
@@ -0,0 +1,468 @@
+use std::{cell::RefCell, fs, io, path::Path, rc::Rc};
+
+use anyhow::{bail, Result};
+
+use super::{
+    scc::{Scc, SccMode},
+    slot::SlotDevice,
+};
+
+const PAGE_SIZE: usize = 0x4000;
+const BANK_SIZE: usize = 0x2000;
+
+// Bank switching schemes used by cartridges bigger than the 48K that fit in
+// the address space. The ones with battery-backed SRAM can't be told apart
+// from the code and have to be chosen on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MapperType {
+    Plain,
+    Konami,
+    KonamiScc,
+    Ascii8,
+    Ascii16,
+    Ascii8Sram,
+    Ascii16Sram,
+    GameMaster2,
+}
+
+impl MapperType {
+    // Guesses the mapper from the bank switch writes (LD (nnnn),A) found in
+    // the code, the same heuristic used by blueMSX
+    pub fn detect(rom: &[u8]) -> MapperType {
+        if rom.len() <= 3 * PAGE_SIZE {
+            return MapperType::Plain;
+        }
+
+        let mut konami = 0;
+        let mut konami_scc = 0;
+        let mut ascii8 = 0;
+        let mut ascii16 = 0;
+
+        for i in 0..rom.len() - 2 {
+            if rom[i] != 0x32 {
+                continue;
+            }
+
+            match u16::from_le_bytes([rom[i + 1], rom[i + 2]]) {
+                0x5000 | 0x9000 | 0xB000 => konami_scc += 1,
+                0x4000 | 0x8000 | 0xA000 => konami += 1,
+                0x6800 | 0x7800 => ascii8 += 1,
+                0x6000 => {
+                    konami += 1;
+                    ascii8 += 1;
+                    ascii16 += 1;
+                }
+                0x7000 => {
+                    konami_scc += 1;
+                    ascii8 += 1;
+                    ascii16 += 1;
+                }
+                0x77FF => ascii16 += 1,
+                _ => {}
+            }
+        }
+
+        // ASCII16 games also write to 0x6000 and 0x7000, so ASCII8 needs at
+        // least one write to its other registers to win
+        if ascii8 > 0 {
+            ascii8 -= 1;
+        }
+
+        let guesses = [
+            (MapperType::KonamiScc, konami_scc),
+            (MapperType::Konami, konami),
+            (MapperType::Ascii8, ascii8),
+            (MapperType::Ascii16, ascii16),
+        ];
+
+        let mut best = (MapperType::Plain, 0);
+        for guess in guesses {
+            if guess.1 > best.1 {
+                best = guess;
+            }
+        }
+
+        match best.0 {
+            // Nothing switches banks, a 64K ROM can still be mapped flat
+            MapperType::Plain if rom.len() > 4 * PAGE_SIZE => MapperType::Ascii16,
+            mapper => mapper,
+        }
+    }
+}
+
+// A ROM cartridge inserted in one of the cartridge slots. MegaROMs show
+// 32K at 0x4000-0xBFFF as four 8K windows into the ROM, switched by writing
+// the bank number to the mapper registers.
+pub struct Cartridge {
+    mapper: MapperType,
+    base: u16,
+    // The address range a plain ROM answers to, ROMs smaller than a page are
+    // mirrored to fill it
+    size: usize,
+    rom: Vec<u8>,
+
+    // 8K bank selected for 0x4000, 0x6000, 0x8000 and 0xA000
+    banks: [usize; 4],
+
+    // Sound chip of Konami SCC cartridges, shared with the audio mixer
+    scc: Option<Rc<RefCell<Scc>>>,
+
+    // Battery-backed RAM, empty when the mapper has none. Each bit of
+    // `sram_windows` shows it instead of the ROM on one 8K window, starting
+    // `sram_offsets` bytes into it.
+    sram: Vec<u8>,
+    sram_windows: u8,
+    sram_offsets: [usize; 4],
+}
+
+impl Cartridge {
+    pub fn new(mut rom: Vec<u8>, mapper: Option<MapperType>) -> Result<Self> {
+        if rom.is_empty() {
+            bail!("The cartridge ROM is empty");
+        }
+
+        let mapper = mapper.unwrap_or_else(|| MapperType::detect(&rom));
+
+        if mapper == MapperType::Plain {
+            if rom.len() > 4 * PAGE_SIZE {
+                bail!("A plain ROM can't be bigger than 64K");
+            }
+
+            let size = rom.len().div_ceil(PAGE_SIZE) * PAGE_SIZE;
+            let base = plain_rom_base(&rom).min((0x10000 - size) as u16);
+
+            return Ok(Cartridge {
+                mapper,
+                base,
+                size,
+                rom,
+                banks: [0; 4],
+                scc: None,
+                sram: Vec::new(),
+                sram_windows: 0,
+                sram_offsets: [0; 4],
+            });
+        }
+
+        // Pad to whole banks, bank numbers wrap around the ROM size
+        rom.resize(rom.len().div_ceil(BANK_SIZE) * BANK_SIZE, 0xFF);
+
+        // ASCII16 starts with both halves of 16K bank 0 on 0x4000-0x7FFF
+        let banks = match mapper {
+            MapperType::Konami | MapperType::KonamiScc | MapperType::GameMaster2 => [0, 1, 2, 3],
+            MapperType::Ascii16 | MapperType::Ascii16Sram => [0, 1, 0, 1],
+            _ => [0; 4],
+        };
+
+        let sram_size = match mapper {
+            MapperType::Ascii8Sram | MapperType::GameMaster2 => 0x2000,
+            MapperType::Ascii16Sram => 0x0800,
+            _ => 0,
+        };
+
+        let scc = match mapper {
+            MapperType::KonamiScc => Some(Rc::new(RefCell::new(Scc::new()))),
+            _ => None,
+        };
+
+        Ok(Cartridge {
+            mapper,
+            base: 0x4000,
+            size: 2 * PAGE_SIZE,
+            rom,
+            banks,
+            scc,
+            sram: vec![0xFF; sram_size],
+            sram_windows: 0,
+            sram_offsets: [0; 4],
+        })
+    }
+
+    pub fn has_sram(&self) -> bool {
+        !self.sram.is_empty()
+    }
+
+    // Restores the SRAM saved by a previous run, if there's one
+    pub fn load_sram(&mut self, path: &Path) -> io::Result<()> {
+        if !self.has_sram() || !path.exists() {
+            return Ok(());
+        }
+
+        let data = fs::read(path)?;
+        let size = data.len().min(self.sram.len());
+        self.sram[..size].copy_from_slice(&data[..size]);
+
+        Ok(())
+    }
+
+    pub fn save_sram(&self, path: &Path) -> io::Result<()> {
+        if !self.has_sram() {
+            return Ok(());
+        }
+
+        fs::write(path, &self.sram)
+    }
+
+    pub fn mapper(&self) -> MapperType {
+        self.mapper
+    }
+
+    pub fn base(&self) -> u16 {
+        self.base
+    }
+
+    pub fn scc(&self) -> Option<Rc<RefCell<Scc>>> {
+        self.scc.clone()
+    }
+
+    // The SCC registers replace the ROM at 0x9800-0x9FFF while bank 0x3F is
+    // selected for 0x8000, the SCC+ ones at 0xB800-0xBFFF while bit 7 of the
+    // bank for 0xA000 is set
+    fn get_scc_offset_by_address(&self, address: u16) -> Option<u8> {
+        let scc = self.scc.as_ref()?;
+
+        match (scc.borrow().mode(), address) {
+            (SccMode::Scc, 0x9800..=0x9FFF) if self.banks[2] & 0x3F == 0x3F => Some(address as u8),
+            (SccMode::SccPlus, 0xB800..=0xBFFF) if self.banks[3] & 0x80 != 0 => Some(address as u8),
+            _ => None,
+        }
+    }
+
+    // Shows the SRAM on a window, or the ROM bank again when `enabled` is
+    // false
+    fn select_sram(&mut self, window: usize, enabled: bool, offset: usize) {
+        if enabled {
+            self.sram_windows |= 1 << window;
+        } else {
+            self.sram_windows &= !(1 << window);
+        }
+        self.sram_offsets[window] = offset;
+    }
+
+    // ASCII mappers select the SRAM with a bank number past the end of the
+    // ROM. Games either set the bit just above the ROM size or write 0x10,
+    // so any bit above the bank numbers counts.
+    fn is_sram_bank(bank: usize, rom_banks: usize) -> bool {
+        bank & !(rom_banks.next_power_of_two() - 1) != 0
+    }
+
+    // Index in `sram` of an address on a window showing it. Game Master 2
+    // shows 4K of its SRAM mirrored across the window.
+    fn get_sram_index_by_address(&self, address: u16) -> usize {
+        let window = Self::get_window_by_address(address);
+        let offset = match self.mapper {
+            MapperType::GameMaster2 => address as usize & 0x0FFF,
+            _ => address as usize & (BANK_SIZE - 1),
+        };
+
+        (self.sram_offsets[window] + offset) % self.sram.len()
+    }
+
+    // Index of the 8K window an address in 0x4000-0xBFFF falls in
+    fn get_window_by_address(address: u16) -> usize {
+        ((address - 0x4000) / BANK_SIZE as u16) as usize
+    }
+}
+
+impl SlotDevice for Cartridge {
+    fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        let bank = value as usize;
+
+        if let Some(scc) = &self.scc {
+            // SCC+ mode register, bit 5 moves the sound registers to 0xB800
+            if let 0xBFFE..=0xBFFF = address {
+                let mode = if value & 0x20 != 0 {
+                    SccMode::SccPlus
+                } else {
+                    SccMode::Scc
+                };
+                scc.borrow_mut().set_mode(mode);
+                return;
+            }
+
+            if let Some(offset) = self.get_scc_offset_by_address(address) {
+                scc.borrow_mut().write(offset, value);
+                return;
+            }
+        }
+
+        let rom_banks = self.rom.len() / BANK_SIZE;
+
+        match (self.mapper, address) {
+            // Konami: 0x4000 is fixed to bank 0, the other windows switch
+            // on writes anywhere in them
+            (MapperType::Konami, 0x6000..=0xBFFF) => {
+                self.banks[Self::get_window_by_address(address)] = bank;
+            }
+            // Konami SCC: 0x5000, 0x7000, 0x9000 and 0xB000
+            (MapperType::KonamiScc, 0x5000..=0xB7FF) if address & 0x1800 == 0x1000 => {
+                self.banks[Self::get_window_by_address(address)] = bank;
+            }
+            // ASCII8: 0x6000, 0x6800, 0x7000 and 0x7800. With SRAM, any bit
+            // above the ROM bank numbers selects it on 0x8000 and 0xA000.
+            (MapperType::Ascii8 | MapperType::Ascii8Sram, 0x6000..=0x7FFF) => {
+                let window = ((address >> 11) & 0x03) as usize;
+                let sram = self.mapper == MapperType::Ascii8Sram
+                    && window >= 2
+                    && Self::is_sram_bank(bank, rom_banks);
+                self.select_sram(window, sram, 0);
+                if !sram {
+                    self.banks[window] = bank;
+                }
+            }
+            // ASCII16: 0x6000 and 0x7000 switch 16K at 0x4000 and 0x8000.
+            // With SRAM, any bit above the ROM bank numbers selects its 2K,
+            // mirrored, on 0x8000.
+            (MapperType::Ascii16 | MapperType::Ascii16Sram, 0x6000..=0x77FF)
+                if address & 0x0800 == 0 =>
+            {
+                let window = ((address >> 12) & 0x01) as usize * 2;
+                let sram = self.mapper == MapperType::Ascii16Sram
+                    && window == 2
+                    && Self::is_sram_bank(bank, rom_banks.div_ceil(2));
+                self.select_sram(window, sram, 0);
+                self.select_sram(window + 1, sram, 0);
+                if !sram {
+                    self.banks[window] = bank * 2;
+                    self.banks[window + 1] = bank * 2 + 1;
+                }
+            }
+            // Game Master 2: 0x6000, 0x8000 and 0xA000, bit 4 selects the
+            // SRAM instead of a ROM bank and bit 5 which 4K of it
+            (MapperType::GameMaster2, 0x6000..=0xAFFF) if address & 0x1000 == 0 => {
+                let window = Self::get_window_by_address(address);
+                let offset = if value & 0x20 != 0 { 0x1000 } else { 0 };
+                self.select_sram(window, value & 0x10 != 0, offset);
+                self.banks[window] = bank & 0x0F;
+            }
+            _ => {}
+        }
+
+        // SRAM is only writable on the upper half of the cartridge, and on
+        // Game Master 2 only at 0xB000-0xBFFF
+        let writable = match self.mapper {
+            MapperType::Ascii8Sram | MapperType::Ascii16Sram => {
+                (0x8000..=0xBFFF).contains(&address)
+            }
+            MapperType::GameMaster2 => (0xB000..=0xBFFF).contains(&address),
+            _ => false,
+        };
+        if writable && self.sram_windows & (1 << Self::get_window_by_address(address)) != 0 {
+            let index = self.get_sram_index_by_address(address);
+            self.sram[index] = value;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        let offset = match address.checked_sub(self.base) {
+            Some(offset) if (offset as usize) < self.size => offset as usize,
+            _ => return 0xFF,
+        };
+
+        if self.mapper == MapperType::Plain {
+            return self.rom[offset % self.rom.len()];
+        }
+
+        if let Some(offset) = self.get_scc_offset_by_address(address) {
+            return self.scc.as_ref().unwrap().borrow().read(offset);
+        }
+
+        if self.sram_windows & (1 << Self::get_window_by_address(address)) != 0 {
+            return self.sram[self.get_sram_index_by_address(address)];
+        }
+
+        let bank_count = self.rom.len() / BANK_SIZE;
+        let bank = self.banks[Self::get_window_by_address(address)] % bank_count;
+        self.rom[bank * BANK_SIZE + offset % BANK_SIZE]
+    }
+}
+
+// Finds the page a plain ROM starts at. The BIOS looks for the "AB" header
+// at the start of pages 1 and 2 and calls INIT, or runs the BASIC program at
+// TEXT when there's no INIT.
+//
+// +--------+-------------------------------+
+// | Offset | Description                   |
+// +--------+-------------------------------+
+// | 0x00   | "AB"                          |
+// | 0x02   | INIT                          |
+// | 0x04   | STATEMENT                     |
+// | 0x06   | DEVICE                        |
+// | 0x08   | TEXT                          |
+// +--------+-------------------------------+
+fn plain_rom_base(rom: &[u8]) -> u16 {
+    let has_header = |offset: usize| rom.get(offset..offset + 2) == Some(b"AB".as_slice());
+    let word = |offset: usize| match rom.get(offset..offset + 2) {
+        Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]),
+        None => 0,
+    };
+
+    // ROMs with code on page 0 carry their header on page 1
+    if !has_header(0) && has_header(PAGE_SIZE) {
+        return 0x0000;
+    }
+
+    if has_header(0) {
+        let init = word(0x02);
+        let text = word(0x08);
+
+        if init != 0 {
+            return init & 0xC000;
+        }
+        if text != 0 {
+            return text & 0xC000;
+        }
+    }
+
+    0x4000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A ROM whose every byte holds the number of the 8K bank it's in
+    fn numbered_rom(size: usize) -> Vec<u8> {
+        (0..size).map(|offset| (offset / BANK_SIZE) as u8).collect()
+    }
+
+    #[test]
+    fn ascii16_starts_with_bank_0_on_page_1() {
+        let rom = numbered_rom(0x20000);
+        let mut cartridge = Cartridge::new(rom.clone(), Some(MapperType::Ascii16)).unwrap();
+
+        assert_eq!(cartridge.read(0x4000), rom[0x0000]);
+        assert_eq!(cartridge.read(0x6000), rom[0x2000]);
+    }
+
+    #[test]
+    fn ascii16_sram_is_selected_with_0x10_on_128k() {
+        let rom = numbered_rom(0x20000);
+        let mut cartridge = Cartridge::new(rom.clone(), Some(MapperType::Ascii16Sram)).unwrap();
+
+        cartridge.write(0x7000, 0x10);
+        cartridge.write(0x8000, 0x42);
+        assert_eq!(cartridge.read(0x8000), 0x42);
+        // 2K mirrored over the whole window
+        assert_eq!(cartridge.read(0x8800), 0x42);
+        assert_eq!(cartridge.read(0xA000), 0x42);
+
+        // Writing a ROM bank number shows the ROM again
+        cartridge.write(0x7000, 0x01);
+        assert_eq!(cartridge.read(0x8000), rom[0x4000]);
+    }
+
+    #[test]
+    fn ascii8_sram_is_selected_past_a_rom_that_is_not_a_power_of_two() {
+        let rom = numbered_rom(0x18000);
+        let mut cartridge = Cartridge::new(rom, Some(MapperType::Ascii8Sram)).unwrap();
+
+        cartridge.write(0x7000, 0x20);
+        cartridge.write(0x8000, 0x42);
+        assert_eq!(cartridge.read(0x8000), 0x42);
+    }
+}
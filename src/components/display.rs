@@ -3,6 +3,7 @@ use sdl2::{pixels::PixelFormatEnum, render::TextureCreator, video::WindowContext
 
 pub struct Display {
     pub sdl_context: sdl2::Sdl,
+    #[allow(dead_code)]
     pub video_subsystem: sdl2::VideoSubsystem,
     pub canvas: sdl2::render::Canvas<sdl2::video::Window>,
     pub texture_creator: TextureCreator<WindowContext>,
@@ -31,9 +32,11 @@ impl Display {
     }
 
     pub fn update_screen(&mut self, screen_buffer: &[Vec<u32>]) {
+        let width = screen_buffer.first().map_or(0, |row| row.len()) as u32;
+        let height = screen_buffer.len() as u32;
         let mut texture = self
             .texture_creator
-            .create_texture_streaming(PixelFormatEnum::ARGB8888, 320, 192)
+            .create_texture_streaming(PixelFormatEnum::ARGB8888, width, height)
             .unwrap();
 
         texture
@@ -1,31 +1,31 @@
+use std::{cell::Cell, rc::Rc};
+
+use tracing::trace;
+
 use super::IoDevice;
 
 pub struct Ppi {
-    register_a: u8,
+    // Shared with the memory, which reads it on every access
+    register_a: Rc<Cell<u8>>,
     register_b: u8,
     register_c: u8,
     control: u8,
-
-    keyboard_row_selected: u8,
 }
 
 impl Ppi {
     pub fn new() -> Self {
         Ppi {
-            register_a: 0,
+            register_a: Rc::new(Cell::new(0)),
             register_b: 0,
             register_c: 0x50, // Everything OFF. Motor and CapsLed = 1 means OFF
             control: 0,
-
-            keyboard_row_selected: 0,
         }
     }
 
-    pub fn reset(&mut self) {
-        self.register_c = 0x50; // Everything OFF. Motor and CapsLed = 1 means OFF
-        self.keyboard_row_selected = 0;
-        self.update_pulse_signal();
-        self.update_caps_led();
+    // Port A selects the primary slot of each 16K page, two bits per page
+    // starting with page 0 in bits 0-1
+    pub fn primary_slot_config(&self) -> Rc<Cell<u8>> {
+        self.register_a.clone()
     }
 
     fn is_port_a_input(&self) -> bool {
@@ -35,45 +35,32 @@ impl Ppi {
     fn is_port_b_input(&self) -> bool {
         self.control & 0b10 == 0
     }
-
-    fn update_pulse_signal(&self) {
-        // TODO: psg.set_pulse_signal((register_c & 0xa0) > 0);
-    }
-
-    fn update_caps_led(&self) {
-        // TODO leds_socket.led_state_changed(0, (~registerC & 0x40) >> 6);
-    }
 }
 
 impl IoDevice for Ppi {
     fn is_valid_port(&self, port: u8) -> bool {
-        matches!(port, 0xA8 | 0xA9 | 0xAA | 0xAB)
+        matches!(port, 0xA8..=0xAB)
     }
 
     fn read(&mut self, port: u8) -> u8 {
         match port {
             0xA8 => {
                 // get primary slot config
-                println!(
-                    "  *** [PPI] Reading from PPI port {:02X} (input? {}) = {:02X}",
+                trace!(
+                    "[ppi] Reading from PPI port {:02X} (input? {}) = {:02X}",
                     port,
                     self.is_port_a_input(),
-                    self.register_a,
+                    self.register_a.get(),
                 );
                 if self.is_port_a_input() {
-                    self.register_a
-                } else {
-                    0xFF
-                }
-            }
-            0xA9 => {
-                // returns the keyboard port
-                if self.is_port_b_input() {
-                    self.register_b
+                    self.register_a.get()
                 } else {
                     0xFF
                 }
             }
+            // returns the keyboard port
+            0xA9 if self.is_port_b_input() => self.register_b,
+            0xA9 => 0xFF,
             0xAA => {
                 // returns register and flags
                 // var mod = registerC ^ val;
@@ -93,16 +80,16 @@ impl IoDevice for Ppi {
         match port {
             0xA8 => {
                 // set primary slot config
-                println!(
-                    "  *** [PPI] Writing '{:02X}' to PPI port 0xA8 (output? {})",
+                trace!(
+                    "[ppi] Writing '{:02X}' to PPI port 0xA8 (output? {})",
                     value,
                     !self.is_port_a_input()
                 );
-                self.register_a = value;
+                self.register_a.set(value);
             }
             0xA9 => {
-                println!(
-                    "  *** [PPI] Writing '{:02X}' to PPI port 0xA9 (output? {})",
+                trace!(
+                    "[ppi] Writing '{:02X}' to PPI port 0xA9 (output? {})",
                     value,
                     !self.is_port_a_input()
                 );
@@ -111,7 +98,7 @@ impl IoDevice for Ppi {
                 // }
             }
             0xAA => {
-                println!("  *** [PPI] Writing '{:02X}' to PPI port 0xAA", value);
+                trace!("[ppi] Writing '{:02X}' to PPI port 0xAA", value);
                 self.register_c = value;
                 // var bit = (val & 0x0e) >>> 1;
                 // if ((val & 0x01) === 0) registerC &= ~(1 << bit);
@@ -122,10 +109,7 @@ impl IoDevice for Ppi {
                 // else if (bit === 6) updateCapsLed();
             }
             0xAB => {
-                println!(
-                    "  *** [PPI] Writing '{:02X}' to PPI port 0xAB (control)",
-                    value
-                );
+                trace!("[ppi] Writing '{:02X}' to PPI port 0xAB (control)", value);
                 self.control = value & 0x7F;
                 let bit_number = (value >> 1) & 0x07;
                 let bit_status = value & 0x01;
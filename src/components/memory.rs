@@ -1,108 +1,120 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::Cell, rc::Rc};
 
-use super::{vdp::TMS9918, IoDevice};
+use super::slot::{EmptySlot, SlotDevice};
 
+// Writing here while page 3 is on an expanded slot selects its secondary
+// slots, reading returns the inverted value
+const SECONDARY_SLOT_REGISTER: u16 = 0xFFFF;
+
+enum PrimarySlot {
+    Single(Box<dyn SlotDevice>),
+    Expanded {
+        subslots: [Box<dyn SlotDevice>; 4],
+        config: u8,
+    },
+}
+
+// The CPU address space, split into four 16K pages that are each mapped to
+// one of the four primary slots by PPI port A (0xA8)
 pub struct Memory {
-    vdp: Rc<RefCell<TMS9918>>,
-    pub data: Vec<u8>,
+    primary_slot_config: Rc<Cell<u8>>,
+    slots: [PrimarySlot; 4],
 }
 
 impl Memory {
-    pub fn new(vdp: Rc<RefCell<TMS9918>>, size: usize) -> Self {
-        let mut data = vec![0xFF; size];
-
-        // fill the addresses from FD9A through FFC9 with C9
-        (0xFD9A..=0xFFC9).for_each(|i| {
-            data[i] = 0xC9;
-        });
-
-        (0x8003..=0xF37F).for_each(|i| {
-            data[i] = 0xFF;
-        });
+    // `primary_slot_config` is PPI port A, from `Ppi::primary_slot_config`
+    pub fn new(primary_slot_config: Rc<Cell<u8>>) -> Self {
+        Memory {
+            primary_slot_config,
+            slots: std::array::from_fn(|_| PrimarySlot::Single(Box::new(EmptySlot))),
+        }
+    }
 
-        Memory { vdp, data }
+    // Plugs a device into a primary slot, replacing what was there
+    pub fn insert(&mut self, slot: u8, device: Box<dyn SlotDevice>) {
+        self.slots[slot as usize] = PrimarySlot::Single(device);
     }
 
-    pub fn read_byte(&self, address: u16) -> u8 {
-        match address {
-            // BIOS ROM
-            0x0000..=0x3FFF => self.data[address as usize],
-            // Cartidge Slot 1
-            0x4000..=0x7FFF => self.data[address as usize],
-            // Cartidge Slot 2
-            0x8000..=0xBFFF => self.data[address as usize],
-            // Main RAM
-            0xC000..=0xFFFF => self.data[address as usize],
+    // Plugs a device into a secondary slot, expanding the primary slot first
+    // if needed
+    pub fn insert_expanded(&mut self, slot: u8, subslot: u8, device: Box<dyn SlotDevice>) {
+        let primary = &mut self.slots[slot as usize];
+        if let PrimarySlot::Single(_) = primary {
+            *primary = PrimarySlot::Expanded {
+                subslots: std::array::from_fn(|_| Box::new(EmptySlot) as Box<dyn SlotDevice>),
+                config: 0,
+            };
+        }
+
+        if let PrimarySlot::Expanded { subslots, .. } = primary {
+            subslots[subslot as usize] = device;
         }
     }
 
-    pub fn read_signed_byte(&self, addr: u16) -> i8 {
-        let unsigned_byte = self.read_byte(addr);
-        unsigned_byte as i8
+    pub fn read_byte(&mut self, address: u16) -> u8 {
+        let page = address >> 14;
+        match &mut self.slots[self.primary_slot(page)] {
+            PrimarySlot::Expanded { config, .. } if address == SECONDARY_SLOT_REGISTER => !*config,
+            PrimarySlot::Expanded { subslots, config } => {
+                subslots[Self::secondary_slot(*config, page)].read(address)
+            }
+            PrimarySlot::Single(device) => device.read(address),
+        }
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
-        match address {
-            0x0000..=0x3FFF => {
-                // Writing to BIOS is typically not allowed
-                panic!("Writing to BIOS is not allowed")
-            }
-            0x4000..=0x7FFF => {
-                // Writing to BASIC is typically not allowed
-                panic!("Writing to BASIC is not allowed")
-            }
-            0x8000..=0xBFFF => {
-                // panic!("Writing to cartidge, does nothing")
-                match address {
-                    0x9800 => {
-                        // Write to VDP Data Register (0x98)
-                        // Implement VRAM write logic here
-                        let mut vdp = self.vdp.as_ref().borrow_mut();
-                        vdp.write(0x98, value);
-                    }
-                    0x9801 => {
-                        // Write to VDP Address Register (0x99)
-                        // Implement VRAM address setting logic here
-                        let mut vdp = self.vdp.as_ref().borrow_mut();
-                        vdp.write(0x99, value);
-                    }
-                    _ => {}
-                }
+        let page = address >> 14;
+        match &mut self.slots[self.primary_slot(page)] {
+            PrimarySlot::Expanded { config, .. } if address == SECONDARY_SLOT_REGISTER => {
+                *config = value;
             }
-            0xC000..=0xDFFF => self.data[address as usize] = value,
-            0xE000..=0xFFFF => {
-                self.data[address as usize] = value;
+            PrimarySlot::Expanded { subslots, config } => {
+                subslots[Self::secondary_slot(*config, page)].write(address, value)
             }
+            PrimarySlot::Single(device) => device.write(address, value),
         }
     }
 
-    pub fn load_bios(&mut self, buffer: &[u8]) -> std::io::Result<()> {
-        let load_address: u16 = 0x0000;
-        for (i, byte) in buffer.iter().enumerate() {
-            let address = load_address.wrapping_add(i as u16);
-            self.data[address as usize] = *byte;
+    pub fn peek(&self, address: u16) -> u8 {
+        let page = address >> 14;
+        match &self.slots[self.primary_slot(page)] {
+            PrimarySlot::Expanded { config, .. } if address == SECONDARY_SLOT_REGISTER => !*config,
+            PrimarySlot::Expanded { subslots, config } => {
+                subslots[Self::secondary_slot(*config, page)].peek(address)
+            }
+            PrimarySlot::Single(device) => device.peek(address),
         }
-
-        Ok(())
     }
 
-    pub fn write_word(&mut self, address: u16, value: u16) {
-        let low_byte = (value & 0x00FF) as u8;
-        let high_byte = ((value & 0xFF00) >> 8) as u8;
-        self.write_byte(address, low_byte);
-        self.write_byte(address + 1, high_byte);
+    fn primary_slot(&self, page: u16) -> usize {
+        let config = self.primary_slot_config.get();
+        ((config >> (page * 2)) & 0x03) as usize
     }
 
-    pub fn read_word(&self, address: u16) -> u16 {
-        let low_byte = self.read_byte(address) as u16;
-        let high_byte = self.read_byte(address + 1) as u16;
-        (high_byte << 8) | low_byte
+    fn secondary_slot(config: u8, page: u16) -> usize {
+        ((config >> (page * 2)) & 0x03) as usize
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::slot::Ram;
+
+    #[test]
+    fn secondary_slot_register_reads_inverted() {
+        // Page 3 on primary slot 3
+        let primary_slot_config = Rc::new(Cell::new(0xC0));
+        let mut memory = Memory::new(primary_slot_config);
+        memory.insert_expanded(3, 2, Box::new(Ram::new(0x10000)));
+
+        // Subslot 2 for page 3
+        memory.write_byte(0xFFFF, 0x80);
+        assert_eq!(memory.read_byte(0xFFFF), 0x7F);
+        assert_eq!(memory.peek(0xFFFF), 0x7F);
 
-    #[allow(unused)]
-    pub fn load_rom(&mut self, start_address: u16, data: &[u8]) {
-        let start = start_address as usize;
-        let end = start + data.len();
-        self.data[start..end].copy_from_slice(data);
+        // The RAM underneath is still there for the rest of page 3
+        memory.write_byte(0xFFFE, 0x42);
+        assert_eq!(memory.read_byte(0xFFFE), 0x42);
     }
 }
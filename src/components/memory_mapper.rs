@@ -0,0 +1,73 @@
+use tracing::trace;
+
+use super::{slot::SlotDevice, IoDevice};
+
+const SEGMENT_SIZE: usize = 0x4000;
+
+// MSX2 memory mapper RAM. Each 16K page of the address space shows the
+// segment selected by its register, written to ports 0xFC (page 0) to 0xFF
+// (page 3).
+pub struct MemoryMapper {
+    data: Vec<u8>,
+    registers: [u8; 4],
+    // Register bits that select a segment, the others read back as 1
+    mask: u8,
+}
+
+impl MemoryMapper {
+    pub fn new(size: usize) -> Self {
+        assert!(
+            (128 * 1024..=4 * 1024 * 1024).contains(&size) && size.is_multiple_of(SEGMENT_SIZE),
+            "Memory mapper size {}K must be a multiple of 16K from 128K to 4096K",
+            size / 1024
+        );
+
+        let segments = size / SEGMENT_SIZE;
+
+        MemoryMapper {
+            data: vec![0xFF; size],
+            // The layout the BIOS sets up, a flat 64K at the top segments
+            registers: [3, 2, 1, 0],
+            mask: (segments.next_power_of_two() - 1) as u8,
+        }
+    }
+
+    // Offset in `data` of an address on the current segment of its page
+    fn get_offset_by_address(&self, address: u16) -> usize {
+        let segments = self.data.len() / SEGMENT_SIZE;
+        let register = self.registers[(address >> 14) as usize];
+        let segment = (register & self.mask) as usize % segments;
+
+        segment * SEGMENT_SIZE + (address as usize & (SEGMENT_SIZE - 1))
+    }
+}
+
+impl SlotDevice for MemoryMapper {
+    fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        let offset = self.get_offset_by_address(address);
+        self.data[offset] = value;
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.data[self.get_offset_by_address(address)]
+    }
+}
+
+impl IoDevice for MemoryMapper {
+    fn is_valid_port(&self, port: u8) -> bool {
+        matches!(port, 0xFC..=0xFF)
+    }
+
+    fn read(&mut self, port: u8) -> u8 {
+        self.registers[(port - 0xFC) as usize] | !self.mask
+    }
+
+    fn write(&mut self, port: u8, value: u8) {
+        trace!("[mapper] Page {} segment {:02X}", port - 0xFC, value);
+        self.registers[(port - 0xFC) as usize] = value;
+    }
+}
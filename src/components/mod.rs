@@ -1,12 +1,17 @@
+pub mod audio;
 pub mod bus;
-pub mod cpu;
+pub mod cartridge;
 pub mod display;
 pub mod input;
 pub mod memory;
+pub mod memory_mapper;
+pub mod scc;
+pub mod slot;
 pub mod sound;
 pub mod vdp;
 
 pub trait IoDevice {
+    // Asked once per port when the device is registered on the bus
     fn is_valid_port(&self, port: u8) -> bool;
     fn read(&mut self, port: u8) -> u8;
     fn write(&mut self, port: u8, data: u8);
@@ -15,14 +20,20 @@ pub trait IoDevice {
 // +-----------+------------------------------------------------+
 // | Range     | Description                                    |
 // +-----------+------------------------------------------------+
-// | 0x00      | VDP: Video Display Processor (TMS9918) - Data  |
-// | 0x01      | VDP: Video Display Processor (TMS9918) - Ctrl  |
-// | 0x10      | PSG: Programmable Sound Generator (AY-3-8910)  |
-// | 0x11      | PSG: Programmable Sound Generator (AY-3-8910)  |
-// | 0x20      | PPI: Peripheral Interface Adapter (8255) - A   |
-// | 0x21      | PPI: Peripheral Interface Adapter (8255) - B   |
-// | 0x22      | PPI: Peripheral Interface Adapter (8255) - C   |
-// | 0x23      | PPI: Peripheral Interface Adapter (8255) - Ctrl|
-// | 0xA0-0xAF | Slot Select Register (Only in some models)     |
-// | 0x98-0x9B | MSX-MIDI (in MSX-MIDI equipped machines)       |
+// | 0x7C-0x7D | FM-PAC / MSX-MUSIC (YM2413)                    |
+// | 0x90-0x91 | Printer                                        |
+// | 0x98      | VDP: Video Display Processor (TMS9918) - Data  |
+// | 0x99      | VDP: Video Display Processor (TMS9918) - Ctrl  |
+// | 0x9A-0x9B | VDP: Palette and indirect registers (V9938)    |
+// |           | MSX1 machines mirror 0x98-0x99 up to 0x9F      |
+// | 0xA0      | PSG: Programmable Sound Generator - Address    |
+// | 0xA1      | PSG: Programmable Sound Generator - Write      |
+// | 0xA2      | PSG: Programmable Sound Generator - Read       |
+// | 0xA8      | PPI: Peripheral Interface (8255) - A, slots    |
+// | 0xA9      | PPI: Peripheral Interface (8255) - B, keyboard |
+// | 0xAA      | PPI: Peripheral Interface (8255) - C           |
+// | 0xAB      | PPI: Peripheral Interface (8255) - Ctrl        |
+// | 0xB4-0xB5 | RTC: Real Time Clock (MSX2)                    |
+// | 0xFC-0xFF | Memory mapper page registers (MSX2)            |
 // +-----------+------------------------------------------------+
+// Ports nobody answers to read 0xFF
@@ -0,0 +1,158 @@
+use tracing::trace;
+
+use super::sound::{SoundSource, SAMPLE_RATE};
+
+// The SCC runs from the MSX system clock
+const CLOCK: f64 = 3_579_545.0;
+
+// Frequencies below this stop the wave instead of producing ultrasound
+const MIN_PERIOD: u16 = 9;
+
+// The original SCC shares the waveform of channels 4 and 5 and has its
+// registers at 0x9800. The SCC+ (SCC-I) has five waveforms and moves the
+// registers to 0xB800 when in SCC+ mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SccMode {
+    Scc,
+    SccPlus,
+}
+
+// Konami's five channel wavetable chip. Each channel plays a 32 byte signed
+// waveform at a 12-bit frequency and a 4-bit volume.
+//
+// Register layout, relative to 0x9800 (SCC) or 0xB800 (SCC+):
+//
+// +-----------+----------------------+----------------------+
+// | Offset    | SCC                  | SCC+                 |
+// +-----------+----------------------+----------------------+
+// | 0x00-0x7F | Waveforms 1-4        | Waveforms 1-4        |
+// | 0x80-0x9F | Frequency and volume | Waveform 5           |
+// | 0xA0-0xBF | -                    | Frequency and volume |
+// | 0xC0-0xDF | -                    | Deformation          |
+// | 0xE0-0xFF | Deformation          | -                    |
+// +-----------+----------------------+----------------------+
+//
+// Unused ranges and the write only frequency and volume registers read 0xFF.
+// Of the deformation register only bit 5 is emulated, it restarts a
+// channel's wave whenever its period is written.
+//
+// Frequency and volume block, mirrored in its upper 16 bytes:
+//
+// +-----------+----------------------------------+
+// | 0x00-0x09 | Period, low 8 and high 4 bits    |
+// | 0x0A-0x0E | Volume                           |
+// | 0x0F      | Channel enable, bit 0-4          |
+// +-----------+----------------------------------+
+pub struct Scc {
+    mode: SccMode,
+    waves: [[i8; 32]; 5],
+    periods: [u16; 5],
+    volumes: [u8; 5],
+    enabled: u8,
+    deformation: u8,
+
+    // Position in each waveform, in steps of 1/32 of the wave
+    phases: [f64; 5],
+}
+
+impl Scc {
+    pub fn new() -> Self {
+        Scc {
+            mode: SccMode::Scc,
+            waves: [[0; 32]; 5],
+            periods: [0; 5],
+            volumes: [0; 5],
+            enabled: 0,
+            deformation: 0,
+            phases: [0.0; 5],
+        }
+    }
+
+    pub fn mode(&self) -> SccMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: SccMode) {
+        self.mode = mode;
+    }
+
+    pub fn read(&self, offset: u8) -> u8 {
+        match (self.mode, offset) {
+            (_, 0x00..=0x7F) => self.get_wave_byte(offset),
+            (SccMode::SccPlus, 0x80..=0x9F) => self.get_wave_byte(offset),
+            (SccMode::Scc, 0xE0..=0xFF) | (SccMode::SccPlus, 0xC0..=0xDF) => self.deformation,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, offset: u8, value: u8) {
+        trace!("[scc] Writing {:02X} to {:02X}", value, offset);
+
+        match (self.mode, offset) {
+            // Channels 4 and 5 share their waveform on the original SCC
+            (SccMode::Scc, 0x60..=0x7F) => {
+                self.waves[3][(offset & 0x1F) as usize] = value as i8;
+                self.waves[4][(offset & 0x1F) as usize] = value as i8;
+            }
+            (SccMode::Scc, 0x00..=0x5F) | (SccMode::SccPlus, 0x00..=0x9F) => {
+                self.waves[(offset >> 5) as usize][(offset & 0x1F) as usize] = value as i8;
+            }
+            (SccMode::Scc, 0x80..=0x9F) | (SccMode::SccPlus, 0xA0..=0xBF) => {
+                self.write_register(offset & 0x0F, value);
+            }
+            (SccMode::Scc, 0xE0..=0xFF) | (SccMode::SccPlus, 0xC0..=0xDF) => {
+                self.deformation = value;
+            }
+            _ => {}
+        }
+    }
+
+    fn get_wave_byte(&self, offset: u8) -> u8 {
+        self.waves[(offset >> 5) as usize][(offset & 0x1F) as usize] as u8
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) {
+        match register {
+            // Period, games rewrite it every frame for vibrato and slides so
+            // the wave only restarts when the deformation register asks to
+            0x00..=0x09 => {
+                let channel = (register >> 1) as usize;
+                let period = self.periods[channel];
+                self.periods[channel] = if register & 0x01 == 0 {
+                    (period & 0x0F00) | value as u16
+                } else {
+                    (period & 0x00FF) | ((value as u16 & 0x0F) << 8)
+                };
+                if self.deformation & 0x20 != 0 {
+                    self.phases[channel] = 0.0;
+                }
+            }
+            0x0A..=0x0E => self.volumes[(register - 0x0A) as usize] = value & 0x0F,
+            0x0F => self.enabled = value & 0x1F,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl SoundSource for Scc {
+    fn generate_sample(&mut self) -> f32 {
+        let mut sample = 0.0;
+
+        for channel in 0..5 {
+            let period = self.periods[channel];
+            if period >= MIN_PERIOD {
+                let steps = CLOCK / SAMPLE_RATE as f64 / (period + 1) as f64;
+                self.phases[channel] = (self.phases[channel] + steps) % 32.0;
+            }
+
+            if self.enabled & (1 << channel) == 0 {
+                continue;
+            }
+
+            let value = self.waves[channel][self.phases[channel] as usize] as f32 / 128.0;
+            sample += value * self.volumes[channel] as f32 / 15.0;
+        }
+
+        sample / 5.0
+    }
+}
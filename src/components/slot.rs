@@ -0,0 +1,106 @@
+use std::{cell::RefCell, rc::Rc};
+
+// A device plugged into a primary or secondary slot. Addresses are the full
+// CPU address, the slot only decides which device sees them.
+pub trait SlotDevice {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+
+    // Reads without side effects, used by the debugger
+    fn peek(&self, address: u16) -> u8;
+}
+
+// Devices that also answer on I/O ports, like the memory mapper, are shared
+// between the slot and the bus
+impl<T: SlotDevice> SlotDevice for Rc<RefCell<T>> {
+    fn read(&mut self, address: u16) -> u8 {
+        self.borrow_mut().read(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.borrow_mut().write(address, value);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.borrow().peek(address)
+    }
+}
+
+// Nothing plugged in, the data bus floats high
+pub struct EmptySlot;
+
+impl SlotDevice for EmptySlot {
+    fn read(&mut self, _address: u16) -> u8 {
+        0xFF
+    }
+
+    fn write(&mut self, _address: u16, _value: u8) {}
+
+    fn peek(&self, _address: u16) -> u8 {
+        0xFF
+    }
+}
+
+// Read-only memory mapped from `base`, such as the BIOS and BASIC
+pub struct Rom {
+    base: u16,
+    data: Vec<u8>,
+}
+
+impl Rom {
+    pub fn new(base: u16, data: Vec<u8>) -> Self {
+        Rom { base, data }
+    }
+}
+
+impl SlotDevice for Rom {
+    fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, _address: u16, _value: u8) {}
+
+    fn peek(&self, address: u16) -> u8 {
+        address
+            .checked_sub(self.base)
+            .and_then(|offset| self.data.get(offset as usize))
+            .copied()
+            .unwrap_or(0xFF)
+    }
+}
+
+// RAM ending at 0xFFFF, a 16K machine only has it on page 3
+pub struct Ram {
+    base: u16,
+    data: Vec<u8>,
+}
+
+impl Ram {
+    pub fn new(size: usize) -> Self {
+        assert!(size <= 0x10000, "RAM size {:#X} is over 64K", size);
+
+        Ram {
+            base: (0x10000 - size) as u16,
+            data: vec![0xFF; size],
+        }
+    }
+}
+
+impl SlotDevice for Ram {
+    fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if let Some(offset) = address.checked_sub(self.base) {
+            self.data[offset as usize] = value;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address.checked_sub(self.base) {
+            Some(offset) => self.data[offset as usize],
+            None => 0xFF,
+        }
+    }
+}
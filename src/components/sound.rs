@@ -4,9 +4,55 @@ use tracing::trace;
 
 use super::IoDevice;
 
+pub const SAMPLE_RATE: u32 = 44_100;
+
+// The PSG runs at half the MSX system clock and its tone, noise and envelope
+// counters advance every 8 of its cycles
+const CLOCK: f64 = 3_579_545.0 / 2.0;
+const TICKS_PER_SAMPLE: f64 = CLOCK / 8.0 / SAMPLE_RATE as f64;
+
+// Register 14 reads the pins of I/O port A instead of what was written to it.
+// On the MSX they are the joystick selected by R15, active low with
+// directions in bits 0-3 and triggers in 4-5, the keyboard layout (bit 6, set
+// for JIS) and the cassette input (bit 7). Nothing is connected.
+const JOYSTICK_IDLE: u8 = 0x3F;
+const KEYBOARD_LAYOUT_JIS: u8 = 0x40;
+const CASSETTE_INPUT: u8 = 0x80;
+
+// Anything that produces audio, sampled at SAMPLE_RATE
+pub trait SoundSource {
+    fn generate_sample(&mut self) -> f32;
+}
+
+// +----------+------------------------------------------------+
+// | Register | Description                                    |
+// +----------+------------------------------------------------+
+// | 0-5      | Tone period A, B and C, fine and coarse (12b)  |
+// | 6        | Noise period (5b)                              |
+// | 7        | Mixer, tone (0-2) and noise (3-5) off, I/O     |
+// | 8-10     | Volume A, B and C, bit 4 uses the envelope     |
+// | 11-12    | Envelope period, fine and coarse (16b)         |
+// | 13       | Envelope shape, CONT/ATT/ALT/HOLD              |
+// | 14-15    | I/O ports A and B                              |
+// +----------+------------------------------------------------+
 pub struct AY38910 {
     registers: [u8; 16],
     selected_register: u8,
+
+    tone_counters: [u16; 3],
+    tone_outputs: [bool; 3],
+
+    noise_counter: u16,
+    noise_shift: u32,
+    noise_output: bool,
+
+    envelope_counter: u32,
+    envelope_step: u8,
+    envelope_attack: bool,
+    envelope_holding: bool,
+
+    // Fraction of a tick carried over to the next sample
+    ticks: f64,
 }
 
 impl AY38910 {
@@ -14,26 +60,157 @@ impl AY38910 {
         Self {
             registers: [0; 16],
             selected_register: 0,
-            // ... (Initialize other fields)
+            tone_counters: [0; 3],
+            tone_outputs: [false; 3],
+            noise_counter: 0,
+            noise_shift: 1,
+            noise_output: false,
+            envelope_counter: 0,
+            envelope_step: 0,
+            envelope_attack: false,
+            envelope_holding: false,
+            ticks: 0.0,
+        }
+    }
+
+    fn get_tone_period_by_channel(&self, channel: usize) -> u16 {
+        let fine = self.registers[channel * 2] as u16;
+        let coarse = (self.registers[channel * 2 + 1] & 0x0F) as u16;
+        ((coarse << 8) | fine).max(1)
+    }
+
+    fn noise_period(&self) -> u16 {
+        (self.registers[6] & 0x1F).max(1) as u16
+    }
+
+    fn envelope_period(&self) -> u32 {
+        ((self.registers[12] as u32) << 8 | self.registers[11] as u32).max(1)
+    }
+
+    fn envelope_volume(&self) -> u8 {
+        if self.envelope_attack {
+            self.envelope_step
+        } else {
+            15 - self.envelope_step
         }
     }
 
-    pub fn generate_sample(&mut self) -> f32 {
-        // Generate a single audio sample
-        todo!()
+    fn restart_envelope(&mut self) {
+        self.envelope_counter = 0;
+        self.envelope_step = 0;
+        self.envelope_attack = self.registers[13] & 0x04 != 0;
+        self.envelope_holding = false;
+    }
+
+    fn tick(&mut self) {
+        for channel in 0..3 {
+            self.tone_counters[channel] += 1;
+            if self.tone_counters[channel] >= self.get_tone_period_by_channel(channel) {
+                self.tone_counters[channel] = 0;
+                self.tone_outputs[channel] = !self.tone_outputs[channel];
+            }
+        }
+
+        // The noise and envelope generators run at half the tone rate
+        self.noise_counter += 1;
+        if self.noise_counter >= self.noise_period() * 2 {
+            self.noise_counter = 0;
+            // 17-bit LFSR with taps on bits 0 and 3
+            let bit = (self.noise_shift ^ (self.noise_shift >> 3)) & 0x01;
+            self.noise_shift = (self.noise_shift >> 1) | (bit << 16);
+            self.noise_output = self.noise_shift & 0x01 != 0;
+        }
+
+        self.envelope_counter += 1;
+        if self.envelope_counter >= self.envelope_period() * 2 {
+            self.envelope_counter = 0;
+            self.step_envelope();
+        }
+    }
+
+    fn step_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+
+        if self.envelope_step < 15 {
+            self.envelope_step += 1;
+            return;
+        }
+
+        let shape = self.registers[13];
+        let cont = shape & 0x08 != 0;
+        let alternate = shape & 0x02 != 0;
+        let hold = shape & 0x01 != 0;
+
+        if !cont {
+            // Shapes 0-7 end silent
+            self.envelope_holding = true;
+            self.envelope_attack = false;
+            self.envelope_step = 15;
+        } else if hold {
+            self.envelope_holding = true;
+            if alternate {
+                self.envelope_attack = !self.envelope_attack;
+            }
+        } else {
+            if alternate {
+                self.envelope_attack = !self.envelope_attack;
+            }
+            self.envelope_step = 0;
+        }
+    }
+}
+
+impl SoundSource for AY38910 {
+    fn generate_sample(&mut self) -> f32 {
+        self.ticks += TICKS_PER_SAMPLE;
+        while self.ticks >= 1.0 {
+            self.ticks -= 1.0;
+            self.tick();
+        }
+
+        let mixer = self.registers[7];
+        let mut sample = 0.0;
+
+        for channel in 0..3 {
+            let tone_off = mixer & (1 << channel) != 0;
+            let noise_off = mixer & (1 << (channel + 3)) != 0;
+            if !((self.tone_outputs[channel] || tone_off) && (self.noise_output || noise_off)) {
+                continue;
+            }
+
+            let amplitude = self.registers[8 + channel];
+            let volume = if amplitude & 0x10 != 0 {
+                self.envelope_volume()
+            } else {
+                amplitude & 0x0F
+            };
+
+            // Each volume step is about 3dB
+            if volume > 0 {
+                sample += 2f32.powf((volume as f32 - 15.0) / 2.0);
+            }
+        }
+
+        sample / 3.0
     }
 }
 
 impl IoDevice for AY38910 {
     fn is_valid_port(&self, port: u8) -> bool {
-        matches!(port, 0xA0 | 0xA1)
+        matches!(port, 0xA0..=0xA2)
     }
 
+    // On the MSX 0xA0 and 0xA1 are write only, the selected register is
+    // read back through 0xA2
     fn read(&mut self, port: u8) -> u8 {
         match port {
-            0xA0 => self.selected_register,
-            0xA1 => self.registers[self.selected_register as usize],
-            _ => 0,
+            0xA2 if self.selected_register == 14 => {
+                JOYSTICK_IDLE | KEYBOARD_LAYOUT_JIS | CASSETTE_INPUT
+            }
+            0xA2 => self.registers[self.selected_register as usize],
+            _ => 0xFF,
         }
     }
 
@@ -50,9 +227,26 @@ impl IoDevice for AY38910 {
                     self.selected_register
                 );
                 self.registers[self.selected_register as usize] = data;
-                // ... (Update the internal state of the PSG based on the new register value)
+
+                // Writing the shape restarts the envelope
+                if self.selected_register == 13 {
+                    self.restart_envelope();
+                }
             }
             _ => {}
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joystick_port_reads_released_after_reset() {
+        let mut psg = AY38910::new();
+
+        psg.write(0xA0, 14);
+        assert_eq!(psg.read(0xA2) & 0x3F, 0x3F);
+    }
+}
@@ -1,75 +1,355 @@
 #![allow(dead_code)]
 
 use tracing::trace;
+use z80::InterruptLine;
 
 use super::IoDevice;
+
+// Lines of the active display, the frame flag is set when the last one ends
+pub const ACTIVE_SCANLINES: u16 = 192;
+pub const SCREEN_WIDTH: usize = 256;
+
+// The 16 TMS9918 colors in ARGB8888, 0 is transparent and shows whatever is
+// behind it
+const PALETTE: [u32; 16] = [
+    0xFF000000, // Transparent
+    0xFF000000, // Black
+    0xFF21C842, // Medium green
+    0xFF5EDC78, // Light green
+    0xFF5455ED, // Dark blue
+    0xFF7D76FC, // Light blue
+    0xFFD4524D, // Dark red
+    0xFF42EBF5, // Cyan
+    0xFFFC5554, // Medium red
+    0xFFFF7978, // Light red
+    0xFFD4C154, // Dark yellow
+    0xFFE6CE80, // Light yellow
+    0xFF21B03B, // Dark green
+    0xFFC95BBA, // Magenta
+    0xFFCCCCCC, // Gray
+    0xFFFFFFFF, // White
+];
+
+// Bits of the registers that exist on the TMS9918, the rest read as 0
+const REGISTER_MASKS: [u8; 8] = [0x03, 0xFB, 0x0F, 0xFF, 0x07, 0x7F, 0x07, 0xFF];
+
+// The display modes selected by M1, M2 (R1) and M3 (R0)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenMode {
+    // Screen 0, 40x24 characters of 6x8
+    Text,
+    // Screen 1, 32x24 tiles with a color per group of 8 characters
+    Graphic1,
+    // Screen 2, 32x24 tiles with a pattern and colors per line of each tile
+    Graphic2,
+    // Screen 3, 64x48 blocks of 4x4
+    Multicolor,
+
+    // The undocumented combinations of the mode bits
+    //
+    // Text with the three pattern banks of Graphic II
+    TextGraphic2,
+    // Multicolor with the three pattern banks of Graphic II
+    MulticolorGraphic2,
+    // M1 and M2, with or without M3: 40 columns of 4 pixels in the text color
+    // and 2 of the backdrop, ignoring VRAM
+    TextMulticolor,
+}
+
+// +----------+------------------------------------------------+
+// | Register | Description                                    |
+// +----------+------------------------------------------------+
+// | 0        | Mode bit M3 (1), external video (0)            |
+// | 1        | 16K (7), blank (6), IE0 (5), M1 (4), M2 (3),   |
+// |          | sprite size (1) and magnification (0)          |
+// | 2        | Name table base, * 0x400                       |
+// | 3        | Color table base, * 0x40                       |
+// | 4        | Pattern generator table base, * 0x800          |
+// | 5        | Sprite attribute table base, * 0x80            |
+// | 6        | Sprite pattern generator table base, * 0x800   |
+// | 7        | Text color (4-7) and backdrop color (0-3)      |
+// +----------+------------------------------------------------+
 pub struct TMS9918 {
     pub vram: Vec<u8>,
-    pub screen_mode: u8,
+    pub registers: [u8; 8],
     pub screen_buffer: Vec<Vec<u32>>,
     pub address_register: u16,
+    // First byte of a control port write, used when the second one arrives
     pub data_latch: u8,
+    // Data port reads return this and fetch the next byte into it
+    pub read_ahead: u8,
     pub status_register: u8,
     pub is_second_write: bool,
+    // Held while the frame flag (status bit 7) and IE0 (R1 bit 5) are set
+    interrupt_line: Option<InterruptLine>,
 }
 
 impl TMS9918 {
     pub fn new() -> Self {
-        const SCREEN_WIDTH: usize = 320;
-        const SCREEN_HEIGHT: usize = 192;
-
-        let screen_buffer = vec![vec![0; SCREEN_WIDTH]; SCREEN_HEIGHT];
+        let screen_buffer = vec![vec![0; SCREEN_WIDTH]; ACTIVE_SCANLINES as usize];
 
         Self {
             vram: vec![0; 16 * 1024], // 16 KB VRAM
-            screen_mode: 0,
+            registers: [0; 8],
             screen_buffer,
             address_register: 0,
             data_latch: 0,
+            read_ahead: 0,
             status_register: 0,
             is_second_write: false,
+            interrupt_line: None,
         }
     }
 
-    pub fn render_scanline(&mut self, scanline: u16) {
-        #[allow(clippy::single_match)]
-        match self.screen_mode {
-            0 => self.render_scanline_text_mode(scanline),
-            // Add other screen modes here
-            _ => (), // Ignore unsupported screen modes
+    pub fn screen_mode(&self) -> ScreenMode {
+        let m1 = self.registers[1] & 0x10 != 0;
+        let m2 = self.registers[1] & 0x08 != 0;
+        let m3 = self.registers[0] & 0x02 != 0;
+
+        match (m1, m2, m3) {
+            (false, false, false) => ScreenMode::Graphic1,
+            (true, false, false) => ScreenMode::Text,
+            (false, true, false) => ScreenMode::Multicolor,
+            (false, false, true) => ScreenMode::Graphic2,
+            (true, false, true) => ScreenMode::TextGraphic2,
+            (false, true, true) => ScreenMode::MulticolorGraphic2,
+            (true, true, _) => ScreenMode::TextMulticolor,
         }
     }
 
-    fn render_scanline_text_mode(&mut self, scanline: u16) {
-        const CHARS_PER_ROW: u16 = 40;
-        const ROWS: u16 = 24;
-        const PATTERN_HEIGHT: u16 = 8;
+    pub fn connect_interrupt(&mut self, interrupt_line: InterruptLine) {
+        self.interrupt_line = Some(interrupt_line);
+    }
+
+    // Called when the last line of the active display has been drawn
+    pub fn start_vblank(&mut self) {
+        self.status_register |= 0x80;
+        self.update_interrupt();
+    }
+
+    fn update_interrupt(&self) {
+        if let Some(interrupt_line) = &self.interrupt_line {
+            let frame = self.status_register & 0x80 != 0;
+            let enabled = self.registers[1] & 0x20 != 0;
+            interrupt_line.set(frame && enabled);
+        }
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) {
+        // Only the low 3 bits select the register on the TMS9918
+        let register = (register & 0x07) as usize;
+        trace!("[vdp] Writing {:02X} to register {}", value, register);
+        self.registers[register] = value & REGISTER_MASKS[register];
 
-        let foreground_color = 0xFFFFFFFF; // White color in RGBA8888 format
-        let background_color = 0xFF0000FF; // Black color in RGBA8888 format
+        // Setting IE0 with the frame flag already up interrupts right away
+        if register == 1 {
+            self.update_interrupt();
+        }
+    }
 
-        if scanline >= ROWS * PATTERN_HEIGHT {
+    fn name_table(&self) -> usize {
+        (self.registers[2] as usize & 0x0F) << 10
+    }
+
+    fn color_table(&self) -> usize {
+        (self.registers[3] as usize) << 6
+    }
+
+    fn pattern_table(&self) -> usize {
+        (self.registers[4] as usize & 0x07) << 11
+    }
+
+    fn backdrop_color(&self) -> u8 {
+        self.registers[7] & 0x0F
+    }
+
+    // Transparent pixels show the backdrop, as there are no sprites yet
+    fn get_color_by_index(&self, index: u8) -> u32 {
+        match index {
+            0 => PALETTE[self.backdrop_color() as usize],
+            _ => PALETTE[index as usize],
+        }
+    }
+
+    pub fn render_scanline(&mut self, scanline: u16) {
+        if scanline >= ACTIVE_SCANLINES {
             return; // Beyond the visible screen area
         }
 
-        let row = scanline / PATTERN_HEIGHT;
-        let y_within_pattern = scanline % PATTERN_HEIGHT;
+        // Everything but the backdrop is hidden while BL (R1 bit 6) is reset
+        let backdrop = self.get_color_by_index(0);
+        self.screen_buffer[scanline as usize].fill(backdrop);
+        if self.registers[1] & 0x40 == 0 {
+            return;
+        }
 
-        for col in 0..CHARS_PER_ROW {
-            let char_index = self.vram[(row * CHARS_PER_ROW + col) as usize];
-            let pattern_offset = (char_index as u16) * PATTERN_HEIGHT + y_within_pattern;
-            let pattern_line = self.vram[pattern_offset as usize];
-
-            for x_within_pattern in 0..6 {
-                let pixel = (pattern_line >> (7 - x_within_pattern)) & 1;
-                let x = (col * 8 + x_within_pattern) as usize;
-                let y = scanline as usize;
-                self.screen_buffer[y][x] = if pixel == 1 {
-                    foreground_color
-                } else {
-                    background_color
-                };
+        match self.screen_mode() {
+            ScreenMode::Text | ScreenMode::TextGraphic2 => self.render_scanline_text_mode(scanline),
+            ScreenMode::Graphic1 => self.render_scanline_graphic1(scanline),
+            ScreenMode::Graphic2 => self.render_scanline_graphic2(scanline),
+            ScreenMode::Multicolor | ScreenMode::MulticolorGraphic2 => {
+                self.render_scanline_multicolor(scanline)
             }
+            ScreenMode::TextMulticolor => self.render_scanline_text_multicolor(scanline),
+        }
+    }
+
+    fn render_scanline_text_mode(&mut self, scanline: u16) {
+        const CHARS_PER_ROW: usize = 40;
+        // The 240 pixels wide screen is centered in the 256 of the others
+        const BORDER: usize = 8;
+
+        let row = scanline as usize / 8;
+        let line = scanline as usize % 8;
+
+        let foreground = self.get_color_by_index(self.registers[7] >> 4);
+        let background = self.get_color_by_index(self.registers[7] & 0x0F);
+
+        for col in 0..CHARS_PER_ROW {
+            let name = self.vram[self.name_table() + row * CHARS_PER_ROW + col] as usize;
+            let pattern_address = match self.screen_mode() {
+                ScreenMode::TextGraphic2 => {
+                    self.graphic2_pattern_address(((row / 8) << 11) | (name << 3) | line)
+                }
+                _ => self.pattern_table() + name * 8 + line,
+            };
+            let pattern = self.vram[pattern_address];
+
+            self.draw_pattern(
+                scanline,
+                BORDER + col * 6,
+                6,
+                pattern,
+                foreground,
+                background,
+            );
+        }
+    }
+
+    fn render_scanline_text_multicolor(&mut self, scanline: u16) {
+        const COLUMNS: usize = 40;
+        const BORDER: usize = 8;
+
+        let foreground = self.get_color_by_index(self.registers[7] >> 4);
+        let background = self.get_color_by_index(self.registers[7] & 0x0F);
+
+        for col in 0..COLUMNS {
+            self.draw_pattern(scanline, BORDER + col * 6, 6, 0xF0, foreground, background);
+        }
+    }
+
+    fn render_scanline_multicolor(&mut self, scanline: u16) {
+        const TILES_PER_ROW: usize = 32;
+
+        let row = scanline as usize / 8;
+        // Each name has 8 bytes of pattern, two per row of the name table
+        // and a byte for each 4 lines
+        let line = (row & 0x03) * 2 + (scanline as usize % 8) / 4;
+
+        for col in 0..TILES_PER_ROW {
+            let name = self.vram[self.name_table() + row * TILES_PER_ROW + col] as usize;
+            let pattern_address = match self.screen_mode() {
+                ScreenMode::MulticolorGraphic2 => {
+                    self.graphic2_pattern_address(((row / 8) << 11) | (name << 3) | line)
+                }
+                _ => self.pattern_table() + name * 8 + line,
+            };
+            // The left block takes the upper nibble and the right block the
+            // lower one
+            let colors = self.vram[pattern_address];
+
+            self.draw_pattern(
+                scanline,
+                col * 8,
+                8,
+                0xF0,
+                self.get_color_by_index(colors >> 4),
+                self.get_color_by_index(colors & 0x0F),
+            );
+        }
+    }
+
+    fn render_scanline_graphic1(&mut self, scanline: u16) {
+        const TILES_PER_ROW: usize = 32;
+
+        let row = scanline as usize / 8;
+        let line = scanline as usize % 8;
+
+        for col in 0..TILES_PER_ROW {
+            let name = self.vram[self.name_table() + row * TILES_PER_ROW + col];
+            let pattern = self.vram[self.pattern_table() + name as usize * 8 + line];
+            // One color byte for every 8 characters
+            let colors = self.vram[self.color_table() + name as usize / 8];
+
+            self.draw_pattern(
+                scanline,
+                col * 8,
+                8,
+                pattern,
+                self.get_color_by_index(colors >> 4),
+                self.get_color_by_index(colors & 0x0F),
+            );
+        }
+    }
+
+    fn render_scanline_graphic2(&mut self, scanline: u16) {
+        const TILES_PER_ROW: usize = 32;
+
+        let row = scanline as usize / 8;
+        let line = scanline as usize % 8;
+        // Each third of the screen has its own 256 patterns and colors
+        let bank = row / 8;
+
+        for col in 0..TILES_PER_ROW {
+            let name = self.vram[self.name_table() + row * TILES_PER_ROW + col] as usize;
+            let index = (bank << 11) | (name << 3) | line;
+            let pattern = self.vram[self.graphic2_pattern_address(index)];
+            let colors = self.vram[self.graphic2_color_address(index)];
+
+            self.draw_pattern(
+                scanline,
+                col * 8,
+                8,
+                pattern,
+                self.get_color_by_index(colors >> 4),
+                self.get_color_by_index(colors & 0x0F),
+            );
+        }
+    }
+
+    // In Graphic II only bit 2 of R4 is the table base, bits 0 and 1 are
+    // ANDed with the bank bits of the address. Games clear them so every
+    // third of the screen uses the first bank.
+    fn graphic2_pattern_address(&self, index: usize) -> usize {
+        let mask = ((self.registers[4] as usize & 0x07) << 11) | 0x7FF;
+        mask & (0x2000 | index)
+    }
+
+    // Likewise bit 7 of R3 is the color table base and bits 0-6 mask the
+    // upper address bits
+    fn graphic2_color_address(&self, index: usize) -> usize {
+        let mask = ((self.registers[3] as usize) << 6) | 0x3F;
+        mask & (0x2000 | index)
+    }
+
+    // Draws the leftmost `width` pixels of a pattern byte, set bits in the
+    // foreground color
+    fn draw_pattern(
+        &mut self,
+        scanline: u16,
+        x: usize,
+        width: usize,
+        pattern: u8,
+        foreground: u32,
+        background: u32,
+    ) {
+        let pixels = &mut self.screen_buffer[scanline as usize][x..x + width];
+        for (bit, pixel) in pixels.iter_mut().enumerate() {
+            *pixel = if pattern & (0x80 >> bit) != 0 {
+                foreground
+            } else {
+                background
+            };
         }
     }
 }
@@ -83,9 +363,12 @@ impl IoDevice for TMS9918 {
         trace!("[vdp] Read from VDP port: {:02X}", port);
         match port {
             0x98 => {
-                // Read from Data port
-                let data = self.vram[self.address_register as usize];
+                // Read from Data port, the byte was fetched by the previous
+                // access
+                let data = self.read_ahead;
+                self.read_ahead = self.vram[self.address_register as usize];
                 self.address_register = self.address_register.wrapping_add(1) & 0x3FFF;
+                self.is_second_write = false;
                 data
             }
             0x99 => {
@@ -93,6 +376,7 @@ impl IoDevice for TMS9918 {
                 let status = self.status_register;
                 self.status_register &= !(0x80 | 0x40 | 0x20); // Clear bits 7, 6, and 5
                 self.is_second_write = false; // Reset the write sequence
+                self.update_interrupt();
                 status
             }
             _ => 0, // Ignore other ports
@@ -109,21 +393,38 @@ impl IoDevice for TMS9918 {
                     data
                 );
                 self.vram[self.address_register as usize] = data;
+                self.read_ahead = data;
                 self.address_register = self.address_register.wrapping_add(1) & 0x3FFF;
+                self.is_second_write = false;
             }
             0x99 => {
                 // Write to Control port
                 trace!("[vdp] Write to VDP control port: {:02X}", data);
                 if self.is_second_write {
-                    // Second write: set high bits of address and command bits
-                    self.address_register =
-                        (self.address_register & 0x00FF) | ((data as u16 & 0x3F) << 8);
                     self.is_second_write = false;
 
-                    // Handle VDP commands based on the data written (e.g., screen mode change, VRAM access, etc.)
-                    // ...
+                    // +-------+-------+------------------------------------+
+                    // | Bit 7 | Bit 6 | Command                            |
+                    // +-------+-------+------------------------------------+
+                    // | 0     | 0     | Set up the address for reading     |
+                    // | 0     | 1     | Set up the address for writing     |
+                    // | 1     | x     | Write the first byte to register   |
+                    // +-------+-------+------------------------------------+
+                    if data & 0x80 != 0 {
+                        self.write_register(data, self.data_latch);
+                    } else {
+                        self.address_register =
+                            ((data as u16 & 0x3F) << 8) | self.data_latch as u16;
+
+                        if data & 0x40 == 0 {
+                            self.read_ahead = self.vram[self.address_register as usize];
+                            self.address_register = self.address_register.wrapping_add(1) & 0x3FFF;
+                        }
+                    }
                 } else {
-                    // First write: set low bits of address and latch data
+                    // First write: the low byte of the address goes in
+                    // right away, and is kept for the second write
+                    self.data_latch = data;
                     self.address_register = (self.address_register & 0xFF00) | (data as u16);
                     self.is_second_write = true;
                 }
@@ -0,0 +1,237 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+
+// Describes an MSX model: its ROMs, what sits in each slot and the VDP. Read
+// from a TOML file such as the ones in `machines/`, paths are relative to
+// the file.
+//
+//     name = "C-BIOS MSX1"
+//     open_msx = "C-BIOS_MSX1"
+//
+//     [vdp]
+//     model = "tms9918"
+//     region = "ntsc"
+//
+//     [[slot]]
+//     slot = 0
+//     type = "rom"
+//     path = "../cbios_main_msx1.rom"
+//
+//     [[slot]]
+//     slot = 3
+//     subslot = 2
+//     type = "memory_mapper"
+//     size = 128
+#[derive(Debug, Deserialize)]
+pub struct Machine {
+    pub name: String,
+
+    // Machine to start when comparing against openMSX
+    #[serde(default)]
+    pub open_msx: Option<String>,
+
+    #[serde(default)]
+    pub vdp: VdpConfig,
+
+    #[serde(rename = "slot")]
+    pub slots: Vec<SlotConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct VdpConfig {
+    #[serde(default)]
+    pub model: VdpModel,
+    #[serde(default)]
+    pub region: Region,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VdpModel {
+    #[default]
+    Tms9918,
+    Tms9929,
+    V9938,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+}
+
+impl Region {
+    // Lines the VDP draws per frame, 60Hz for NTSC and 50Hz for PAL
+    pub fn scanlines_per_frame(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal => 313,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SlotConfig {
+    pub slot: u8,
+    #[serde(default)]
+    pub subslot: Option<u8>,
+    #[serde(flatten)]
+    pub device: DeviceConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DeviceConfig {
+    // ROMs in the same slot are merged, so BIOS and BASIC or a logo ROM can
+    // be separate files
+    Rom {
+        path: PathBuf,
+        #[serde(default)]
+        base: u16,
+    },
+    // Plain RAM, in kilobytes and ending at 0xFFFF
+    Ram {
+        size: usize,
+    },
+    // MSX2 memory mapper, in kilobytes
+    MemoryMapper {
+        size: usize,
+    },
+    // An external slot, filled by --cart1 and --cart2 in the order they're
+    // declared
+    Cartridge,
+}
+
+impl Machine {
+    pub fn load(path: &Path) -> Result<Machine> {
+        let data = fs::read_to_string(path)
+            .map_err(|err| anyhow!("Failed to read machine {:?}: {}", path, err))?;
+        let mut machine: Machine = toml::from_str(&data)
+            .map_err(|err| anyhow!("Failed to parse machine {:?}: {}", path, err))?;
+
+        // ROM paths are relative to the machine file
+        let directory = path.parent().unwrap_or(Path::new("."));
+        for entry in &mut machine.slots {
+            if let DeviceConfig::Rom { path, .. } = &mut entry.device {
+                *path = directory.join(&path);
+            }
+        }
+
+        machine.validate()?;
+
+        Ok(machine)
+    }
+
+    // The machine used without --machine: a BIOS in slot 0, cartridges in
+    // slots 1 and 2 and the RAM in slot 3
+    pub fn msx1(bios: PathBuf, memory_mapper: Option<usize>) -> Result<Machine> {
+        let ram = match memory_mapper {
+            Some(size) => DeviceConfig::MemoryMapper { size },
+            None => DeviceConfig::Ram { size: 64 },
+        };
+
+        let slot = |slot, device| SlotConfig {
+            slot,
+            subslot: None,
+            device,
+        };
+
+        let machine = Machine {
+            name: "MSX1".to_string(),
+            open_msx: Some("HOTBIT".to_string()),
+            vdp: VdpConfig::default(),
+            slots: vec![
+                slot(
+                    0,
+                    DeviceConfig::Rom {
+                        path: bios,
+                        base: 0,
+                    },
+                ),
+                slot(1, DeviceConfig::Cartridge),
+                slot(2, DeviceConfig::Cartridge),
+                slot(3, ram),
+            ],
+        };
+        machine.validate()?;
+
+        Ok(machine)
+    }
+
+    fn validate(&self) -> Result<()> {
+        // Whether each slot holds ROMs, several can share a slot but
+        // anything else needs its own
+        let mut occupied = HashMap::new();
+        let mut expanded = HashSet::new();
+        let mut single = HashSet::new();
+
+        for entry in &self.slots {
+            if entry.slot > 3 || entry.subslot.is_some_and(|subslot| subslot > 3) {
+                bail!("Invalid slot {}", entry.describe());
+            }
+
+            match entry.subslot {
+                Some(_) => expanded.insert(entry.slot),
+                None => single.insert(entry.slot),
+            };
+            if expanded.contains(&entry.slot) && single.contains(&entry.slot) {
+                bail!(
+                    "Slot {} is used both as expanded and not expanded",
+                    entry.slot
+                );
+            }
+
+            let is_rom = matches!(entry.device, DeviceConfig::Rom { .. });
+            if let Some(had_rom) = occupied.insert((entry.slot, entry.subslot), is_rom) {
+                if !(had_rom && is_rom) {
+                    bail!("Slot {} has more than one device", entry.describe());
+                }
+            }
+
+            match entry.device {
+                DeviceConfig::Ram { size } if size == 0 || size > 64 => {
+                    bail!("RAM in slot {} must be 1K to 64K", entry.describe())
+                }
+                DeviceConfig::MemoryMapper { size }
+                    if !(128..=4096).contains(&size) || !size.is_multiple_of(16) =>
+                {
+                    bail!(
+                        "Memory mapper in slot {} must be a multiple of 16K from 128K to 4096K",
+                        entry.describe()
+                    )
+                }
+                _ => {}
+            }
+        }
+
+        // Every memory mapper answers to ports 0xFC-0xFF
+        let memory_mappers = self
+            .slots
+            .iter()
+            .filter(|entry| matches!(entry.device, DeviceConfig::MemoryMapper { .. }))
+            .count();
+        if memory_mappers > 1 {
+            bail!("Only one memory mapper is supported, as they share ports 0xFC-0xFF");
+        }
+
+        Ok(())
+    }
+}
+
+impl SlotConfig {
+    // "1" or "3-2"
+    pub fn describe(&self) -> String {
+        match self.subslot {
+            Some(subslot) => format!("{}-{}", self.slot, subslot),
+            None => self.slot.to_string(),
+        }
+    }
+}
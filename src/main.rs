@@ -1,21 +1,51 @@
 mod components;
-mod internal_state;
+mod machine;
 mod msx;
 mod open_msx;
 
 use std::path::PathBuf;
 
+use anyhow::bail;
 use clap::Parser;
+use components::cartridge::MapperType;
 #[allow(unused_imports)]
-use components::{cpu::Z80, input::Ppi, memory::Memory, sound::AY38910, vdp::TMS9918};
+use components::{input::Ppi, memory::Memory, sound::AY38910, vdp::TMS9918};
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
-use crate::msx::Msx;
+use crate::{machine::Machine, msx::Msx};
 
 #[derive(Parser, Debug)]
 pub struct Cli {
-    rom_path: PathBuf,
+    /// BIOS ROM of the default MSX1 machine, when not using --machine
+    rom_path: Option<PathBuf>,
 
+    /// Machine description, see the files in machines/
+    #[clap(long)]
+    machine: Option<PathBuf>,
+
+    /// ROM inserted in cartridge slot 1
+    #[clap(long)]
+    cart1: Option<PathBuf>,
+
+    /// ROM inserted in cartridge slot 2
+    #[clap(long)]
+    cart2: Option<PathBuf>,
+
+    /// Overrides the detected mapper of the cartridge in slot 1
+    #[clap(long, value_enum)]
+    mapper1: Option<MapperType>,
+
+    /// Overrides the detected mapper of the cartridge in slot 2
+    #[clap(long, value_enum)]
+    mapper2: Option<MapperType>,
+
+    /// Replaces the 64K of RAM in slot 3 of the default machine with a memory
+    /// mapper of this many kilobytes (128 to 4096)
+    #[clap(long, conflicts_with = "machine")]
+    memory_mapper: Option<usize>,
+
+    /// Stops the emulation after this many T-states (3,579,545 per second),
+    /// not instructions
     #[clap(short = 'c', long)]
     max_cycles: Option<u64>,
 
@@ -30,6 +60,10 @@ pub struct Cli {
 
     #[clap(short = 'm', long)]
     break_on_mismatch: bool,
+
+    /// Logs writes to I/O ports no device answers to
+    #[clap(long)]
+    log_unmapped_io: bool,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -49,9 +83,19 @@ fn main() -> anyhow::Result<()> {
     //     .filter(None, LevelFilter::Trace)
     //     .init();
 
-    let mut msx = Msx::new(&cli);
-    msx.load_bios(cli.rom_path)
-        .expect("Failed to load the BIOS");
+    let machine = match (&cli.machine, &cli.rom_path) {
+        (Some(path), _) => Machine::load(path)?,
+        (None, Some(bios)) => Machine::msx1(bios.clone(), cli.memory_mapper)?,
+        (None, None) => bail!("Either a BIOS ROM or --machine is required"),
+    };
+
+    let mut msx = Msx::new(&cli, &machine)?;
+    if let Some(path) = cli.cart1 {
+        msx.load_cartridge(1, path, cli.mapper1)?;
+    }
+    if let Some(path) = cli.cart2 {
+        msx.load_cartridge(2, path, cli.mapper2)?;
+    }
 
     msx.max_cycles = cli.max_cycles;
     msx.track_flags = cli.track_flags;
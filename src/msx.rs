@@ -1,24 +1,50 @@
-use std::{cell::RefCell, fs::File, io::Read, path::PathBuf, rc::Rc};
+use std::{cell::RefCell, collections::BTreeMap, fs::File, io::Read, path::PathBuf, rc::Rc};
 
-use tracing::{debug, info};
+use anyhow::{anyhow, bail};
+use tracing::{debug, info, warn};
+
+use z80::Z80;
 
 use crate::{
     components::{
-        cpu::Z80, display::Display, input::Ppi, memory::Memory, sound::AY38910, vdp::TMS9918,
+        audio::Audio,
+        bus::Bus,
+        cartridge::{Cartridge, MapperType},
+        display::Display,
+        input::Ppi,
+        memory::Memory,
+        memory_mapper::MemoryMapper,
+        slot::{Ram, Rom, SlotDevice},
+        sound::AY38910,
+        vdp::{ACTIVE_SCANLINES, TMS9918},
     },
+    machine::{DeviceConfig, Machine, VdpModel},
     open_msx::Client,
     Cli,
 };
 
+// The Z80 runs at 3.579545 MHz and each line the VDP draws takes 228 CPU
+// T-states, 262 lines per frame on NTSC (~59.94 frames per second)
+const T_STATES_PER_SCANLINE: u32 = 228;
+
 pub struct Msx {
-    cpu: Z80,
+    cpu: Z80<Bus>,
     vdp: Rc<RefCell<TMS9918>>,
     #[allow(unused)]
     psg: Rc<RefCell<AY38910>>,
 
     display: Display,
+    audio: Audio,
+
+    // Slot and subslot of each external slot, in --cartN order
+    cartridge_slots: Vec<(u8, Option<u8>)>,
+    // Cartridges with SRAM and the .sav file it's kept in between runs
+    saved_cartridges: Vec<(Rc<RefCell<Cartridge>>, PathBuf)>,
 
     current_scanline: u16,
+    scanline_t_states: u32,
+    scanlines_per_frame: u16,
+    open_msx_machine: Option<String>,
 
     // debug options
     pub breakpoints: Vec<u16>,
@@ -29,17 +55,82 @@ pub struct Msx {
 }
 
 impl Msx {
-    pub fn new(cli: &Cli) -> Self {
+    pub fn new(cli: &Cli, machine: &Machine) -> anyhow::Result<Self> {
+        info!("Machine: {}", machine.name);
+        if machine.vdp.model == VdpModel::V9938 {
+            warn!("The V9938 is not emulated yet, using a TMS9918 instead");
+        }
+
         let vdp = Rc::new(RefCell::new(TMS9918::new()));
         let psg = Rc::new(RefCell::new(AY38910::new()));
         let ppi = Rc::new(RefCell::new(Ppi::new()));
 
         let display = Display::new(256, 192);
+        let mut audio = Audio::new(&display.sdl_context);
+        audio.add_source(psg.clone());
+
+        let mut memory = Memory::new(ppi.borrow().primary_slot_config());
+        let mut memory_mappers = Vec::new();
+        let mut cartridge_slots = Vec::new();
+        // 64K images of the ROMs in each slot
+        let mut roms: BTreeMap<(u8, Option<u8>), Vec<u8>> = BTreeMap::new();
+
+        for entry in &machine.slots {
+            let device: Box<dyn SlotDevice> = match &entry.device {
+                DeviceConfig::Rom { path, base } => {
+                    let data = std::fs::read(path)
+                        .map_err(|err| anyhow!("Failed to read ROM {:?}: {}", path, err))?;
+                    let start = *base as usize;
+                    if start + data.len() > 0x10000 {
+                        bail!("ROM {:?} doesn't fit at {:#06X}", path, base);
+                    }
+
+                    let image = roms
+                        .entry((entry.slot, entry.subslot))
+                        .or_insert_with(|| vec![0xFF; 0x10000]);
+                    image[start..start + data.len()].copy_from_slice(&data);
+                    continue;
+                }
+                DeviceConfig::Ram { size } => Box::new(Ram::new(size * 1024)),
+                DeviceConfig::MemoryMapper { size } => {
+                    let memory_mapper = Rc::new(RefCell::new(MemoryMapper::new(size * 1024)));
+                    memory_mappers.push(memory_mapper.clone());
+                    Box::new(memory_mapper)
+                }
+                DeviceConfig::Cartridge => {
+                    cartridge_slots.push((entry.slot, entry.subslot));
+                    continue;
+                }
+            };
+            insert_device(&mut memory, entry.slot, entry.subslot, device);
+        }
+        for ((slot, subslot), image) in roms {
+            insert_device(
+                &mut memory,
+                slot,
+                subslot,
+                Box::new(Rom::new(0x0000, image)),
+            );
+        }
+
+        let mut bus = Bus::new(memory);
+        bus.log_unmapped_writes = cli.log_unmapped_io;
+        // The MSX1 VDP only decodes A0 of the 0x98-0x9F range, the V9938
+        // takes 0x98-0x9B
+        let vdp_mirror_mask = match machine.vdp.model {
+            VdpModel::Tms9918 | VdpModel::Tms9929 => 0x06,
+            VdpModel::V9938 => 0x00,
+        };
+        bus.register_mirrored_device(vdp.clone(), vdp_mirror_mask)?;
+        bus.register_device(psg.clone())?;
+        bus.register_device(ppi)?;
+        for memory_mapper in memory_mappers {
+            bus.register_device(memory_mapper)?;
+        }
 
-        let mut cpu = Z80::new(Memory::new(vdp.clone(), 64 * 1024));
-        cpu.register_device(vdp.clone());
-        cpu.register_device(psg.clone());
-        cpu.register_device(ppi);
+        let mut cpu = Z80::new(bus);
+        cpu.m1_wait_states = 1;
+        vdp.borrow_mut().connect_interrupt(cpu.interrupt_line());
 
         let mut breakpoints: Vec<u16> = Vec::new();
         for breakpoint in &cli.breakpoint {
@@ -47,18 +138,24 @@ impl Msx {
             breakpoints.push(breakpoint);
         }
 
-        Self {
+        Ok(Self {
             cpu,
             vdp,
             psg,
             display,
+            audio,
+            cartridge_slots,
+            saved_cartridges: Vec::new(),
             current_scanline: 0,
+            scanline_t_states: 0,
+            scanlines_per_frame: machine.vdp.region.scanlines_per_frame(),
+            open_msx_machine: machine.open_msx.clone(),
             max_cycles: None,
             breakpoints,
             open_msx: cli.open_msx,
             break_on_mismatch: cli.break_on_mismatch,
             track_flags: false,
-        }
+        })
     }
 
     pub fn add_breakpoint(&mut self, address: u16) {
@@ -73,17 +170,52 @@ impl Msx {
 
         for (i, byte) in buffer.iter().enumerate() {
             let address = load_address.wrapping_add(i as u16);
-            self.cpu.memory.write_byte(address, *byte);
+            self.cpu.bus.memory.write_byte(address, *byte);
         }
 
         Ok(())
     }
 
-    pub fn load_bios(&mut self, path: PathBuf) -> std::io::Result<()> {
-        let mut file = File::open(path)?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)?;
-        self.cpu.memory.load_bios(&buffer)?;
+    // Inserts a ROM in the external slot `number`, counting from 1
+    pub fn load_cartridge(
+        &mut self,
+        number: usize,
+        path: PathBuf,
+        mapper: Option<MapperType>,
+    ) -> anyhow::Result<()> {
+        let Some(&(slot, subslot)) = self.cartridge_slots.get(number - 1) else {
+            bail!("The machine has no cartridge slot {}", number);
+        };
+
+        let rom = std::fs::read(&path)?;
+        let size = rom.len();
+        let mut cartridge = Cartridge::new(rom, mapper)?;
+
+        info!(
+            "Cartridge {:?} in slot {}{}: {}K, {:?} mapper at {:#06X}",
+            path,
+            slot,
+            subslot
+                .map(|subslot| format!("-{}", subslot))
+                .unwrap_or_default(),
+            size / 1024,
+            cartridge.mapper(),
+            cartridge.base()
+        );
+        if let Some(scc) = cartridge.scc() {
+            self.audio.add_source(scc);
+        }
+
+        if cartridge.has_sram() {
+            let sav_path = path.with_extension("sav");
+            cartridge.load_sram(&sav_path)?;
+
+            let cartridge = Rc::new(RefCell::new(cartridge));
+            self.saved_cartridges.push((cartridge.clone(), sav_path));
+            insert_device(&mut self.cpu.bus.memory, slot, subslot, Box::new(cartridge));
+        } else {
+            insert_device(&mut self.cpu.bus.memory, slot, subslot, Box::new(cartridge));
+        }
 
         Ok(())
     }
@@ -93,8 +225,12 @@ impl Msx {
 
         info!("OpenMSX: {}", self.open_msx);
         let mut client = if self.open_msx {
+            let Some(machine) = &self.open_msx_machine else {
+                bail!("The machine has no openMSX counterpart to compare with");
+            };
+
             let mut client = Client::new()?;
-            client.init()?;
+            client.init(machine)?;
             println!("Connected to openMSX! (type 'quit' to exit)");
 
             Some(client)
@@ -120,7 +256,7 @@ impl Msx {
                             let our_status = self.cpu.get_internal_state();
 
                             // println!(" opcode: {:#04X}", last_opcode);
-                            // println!(" opcode: {:#04X}", self.cpu.memory.read_byte(self.cpu.pc));
+                            // println!(" opcode: {:#04X}", self.cpu.bus.memory.read_byte(self.cpu.pc));
                             println!("   ours: {}", our_status);
 
                             if let Some(client) = &mut client {
@@ -138,16 +274,16 @@ impl Msx {
                 }
             }
 
-            let last_opcode = self.cpu.memory.read_byte(self.cpu.pc);
+            let last_opcode = self.cpu.bus.memory.peek(self.cpu.pc);
             debug!(
                 "running pc = {:#06X} opcode = {:#04X}",
                 self.cpu.pc, last_opcode
             );
-            self.cpu.execute_cycle();
+            let t_states = self.cpu.execute_cycle();
             debug!(
                 "    ran pc = {:#06X} opcode = {:#04X}",
                 self.cpu.pc,
-                self.cpu.memory.read_byte(self.cpu.pc)
+                self.cpu.bus.memory.peek(self.cpu.pc)
             );
 
             let mut stop = false;
@@ -210,7 +346,7 @@ impl Msx {
                             let our_status = self.cpu.get_internal_state();
 
                             // println!(" opcode: {:#04X}", last_opcode);
-                            // println!(" opcode: {:#04X}", self.cpu.memory.read_byte(self.cpu.pc));
+                            // println!(" opcode: {:#04X}", self.cpu.bus.memory.read_byte(self.cpu.pc));
                             println!("   ours: {}", our_status);
 
                             if let Some(client) = &mut client {
@@ -224,7 +360,7 @@ impl Msx {
                             let command = command.split(' ').collect::<Vec<&str>>();
                             if command[0].starts_with("0x") {
                                 let address = u16::from_str_radix(&command[0][2..], 16).unwrap();
-                                let our_status = self.cpu.memory.read_byte(address);
+                                let our_status = self.cpu.bus.memory.peek(address);
 
                                 if let Some(client) = &mut client {
                                     let emu_status = client
@@ -244,7 +380,7 @@ impl Msx {
                             if command[0].starts_with("0x") {
                                 let address = u16::from_str_radix(&command[0][2..], 16).unwrap();
                                 let value = u8::from_str_radix(&command[1][2..], 16).unwrap();
-                                self.cpu.memory.write_byte(address, value);
+                                self.cpu.bus.memory.write_byte(address, value);
 
                                 if let Some(client) = &mut client {
                                     client.send(&format!(
@@ -271,17 +407,22 @@ impl Msx {
                 }
             }
 
-            if self.cpu.halted {
-                break;
-            }
+            self.audio.run(t_states);
+
+            self.scanline_t_states += t_states;
+            while self.scanline_t_states >= T_STATES_PER_SCANLINE {
+                self.scanline_t_states -= T_STATES_PER_SCANLINE;
 
-            let mut vdp = self.vdp.borrow_mut();
-            vdp.render_scanline(self.current_scanline);
+                let mut vdp = self.vdp.borrow_mut();
+                vdp.render_scanline(self.current_scanline);
 
-            self.current_scanline = (self.current_scanline + 1) % 262;
-            if self.current_scanline == 0 {
-                self.display.update_screen(&vdp.screen_buffer);
-                // vdp.render_frame();
+                // The VDP raises its interrupt as soon as the active display
+                // ends, the CPU takes it before its next instruction
+                self.current_scanline = (self.current_scanline + 1) % self.scanlines_per_frame;
+                if self.current_scanline == ACTIVE_SCANLINES {
+                    vdp.start_vblank();
+                    self.display.update_screen(&vdp.screen_buffer);
+                }
             }
         }
 
@@ -292,3 +433,23 @@ impl Msx {
         Ok(())
     }
 }
+
+// SRAM is saved however the emulation ends, including errors and the panic
+// when --max-cycles is reached
+impl Drop for Msx {
+    fn drop(&mut self) {
+        for (cartridge, path) in &self.saved_cartridges {
+            info!("Saving SRAM to {:?}", path);
+            if let Err(err) = cartridge.borrow().save_sram(path) {
+                warn!("Failed to save SRAM to {:?}: {}", path, err);
+            }
+        }
+    }
+}
+
+fn insert_device(memory: &mut Memory, slot: u8, subslot: Option<u8>, device: Box<dyn SlotDevice>) {
+    match subslot {
+        Some(subslot) => memory.insert_expanded(slot, subslot, device),
+        None => memory.insert(slot, device),
+    }
+}
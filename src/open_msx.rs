@@ -8,7 +8,7 @@ use tracing::{event, span, Level};
 use walkdir::WalkDir;
 use xml::reader::{EventReader, XmlEvent};
 
-use crate::internal_state::InternalState;
+use z80::InternalState;
 
 pub enum Response {
     Ok(String),
@@ -16,6 +16,7 @@ pub enum Response {
 }
 
 pub struct Client {
+    #[allow(dead_code)]
     pub socket: UnixStream,
     pub reader: EventReader<UnixStream>,
     pub writer: BufWriter<UnixStream>,
@@ -52,9 +53,9 @@ impl Client {
         }
     }
 
-    pub fn init(&mut self) -> Result<()> {
+    pub fn init(&mut self, machine: &str) -> Result<()> {
         self.send("set power off")?;
-        self.send("machine HOTBIT")?;
+        self.send(&format!("machine {}", machine))?;
         self.send("debug set_bp 0x0000")?;
         self.send("set power on")?;
         Ok(())
@@ -76,6 +77,12 @@ impl Client {
         let e = self.send("reg e")?.parse()?;
         let h = self.send("reg h")?.parse()?;
         let l = self.send("reg l")?.parse()?;
+        let i = self.send("reg i")?.parse()?;
+        let r = self.send("reg r")?.parse()?;
+        let af_alt = self.send("reg af2")?.parse()?;
+        let bc_alt = self.send("reg bc2")?.parse()?;
+        let de_alt = self.send("reg de2")?.parse()?;
+        let hl_alt = self.send("reg hl2")?.parse()?;
         let hl = self.send("reg hl")?.parse()?;
         let hl_contents = self
             .send(&format!("debug read memory 0x{:04X}", hl))?
@@ -95,6 +102,12 @@ impl Client {
             e,
             h,
             l,
+            i,
+            r,
+            af_alt,
+            bc_alt,
+            de_alt,
+            hl_alt,
             hl,
             hl_contents,
             opcode,
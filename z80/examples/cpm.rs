@@ -0,0 +1,125 @@
+//! Runs a CP/M .COM program, such as ZEXDOC or ZEXALL, on a bare Z80 with
+//! 64K of RAM. Only the BDOS console output calls are emulated, which is all
+//! the instruction exercisers need.
+//!
+//!     cargo run --release -p z80 --example cpm -- zexdoc.com
+//!
+//! Exits with a non-zero status if the program printed "ERROR".
+
+use std::{
+    env, fs,
+    io::{self, Write},
+    process,
+};
+
+use z80::{Bus, Z80};
+
+// Programs are loaded at the start of the Transient Program Area
+const TPA: u16 = 0x0100;
+const BDOS: u16 = 0x0005;
+
+// Top of the TPA, programs read it from the BDOS jump at 0x0005 to set up
+// their stack
+const BDOS_ADDRESS: u16 = 0xFE00;
+
+struct Ram {
+    data: Vec<u8>,
+}
+
+impl Bus for Ram {
+    fn read(&mut self, address: u16) -> u8 {
+        self.data[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.data[address as usize] = value;
+    }
+
+    fn input(&mut self, _port: u16) -> u8 {
+        0xFF
+    }
+
+    fn output(&mut self, _port: u16, _value: u8) {}
+
+    fn peek(&self, address: u16) -> u8 {
+        self.data[address as usize]
+    }
+}
+
+fn main() {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("Usage: cpm <program.com>");
+        process::exit(2);
+    };
+
+    let program = match fs::read(&path) {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!("Failed to read {}: {}", path, err);
+            process::exit(2);
+        }
+    };
+
+    let mut ram = Ram {
+        data: vec![0; 0x10000],
+    };
+    for (i, byte) in program.iter().enumerate() {
+        ram.data[TPA as usize + i] = *byte;
+    }
+
+    // JP BDOS_ADDRESS at 0x0005 and a RET there, the call itself is trapped
+    ram.data[BDOS as usize] = 0xC3;
+    ram.data[BDOS as usize + 1] = BDOS_ADDRESS as u8;
+    ram.data[BDOS as usize + 2] = (BDOS_ADDRESS >> 8) as u8;
+    ram.data[BDOS_ADDRESS as usize] = 0xC9;
+
+    let mut cpu = Z80::new(ram);
+    cpu.pc = TPA;
+    cpu.sp = BDOS_ADDRESS;
+
+    let mut output = String::new();
+    let mut t_states: u64 = 0;
+
+    loop {
+        match cpu.pc {
+            // Warm boot, the program is done
+            0x0000 => break,
+            BDOS => {
+                bdos(&mut cpu, &mut output);
+            }
+            _ => {}
+        }
+
+        t_states += cpu.execute_cycle() as u64;
+    }
+
+    println!();
+    println!("Finished after {} T-states", t_states);
+
+    if output.contains("ERROR") {
+        process::exit(1);
+    }
+}
+
+// Emulates the BDOS function in C, execution then continues at the RET of
+// the BDOS entry point
+fn bdos(cpu: &mut Z80<Ram>, output: &mut String) {
+    let start = output.len();
+
+    match cpu.c {
+        // C_WRITE: print the character in E
+        2 => output.push(cpu.e as char),
+        // C_WRITESTR: print the '$' terminated string at DE
+        9 => {
+            let mut address = (cpu.d as u16) << 8 | cpu.e as u16;
+            while cpu.bus.peek(address) != b'$' {
+                output.push(cpu.bus.peek(address) as char);
+                address = address.wrapping_add(1);
+            }
+        }
+        function => eprintln!("Unsupported BDOS function {}", function),
+    }
+
+    print!("{}", &output[start..]);
+    io::stdout().flush().unwrap();
+}
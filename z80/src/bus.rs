@@ -0,0 +1,30 @@
+/// Everything the CPU sees of the machine it is plugged into.
+pub trait Bus {
+    /// Reads a byte from memory.
+    fn read(&mut self, address: u16) -> u8;
+
+    /// Writes a byte to memory.
+    fn write(&mut self, address: u16, value: u8);
+
+    /// Reads a byte from an I/O port. The CPU puts a 16-bit address on the
+    /// bus, machines that decode only 8 bits should use the low byte.
+    fn input(&mut self, port: u16) -> u8;
+
+    /// Writes a byte to an I/O port.
+    fn output(&mut self, port: u16, value: u8);
+
+    /// Opcode fetch (M1) cycle. Defaults to a regular memory read.
+    fn fetch_opcode(&mut self, address: u16) -> u8 {
+        self.read(address)
+    }
+
+    /// Returns the byte the interrupting device puts on the data bus when an
+    /// interrupt is acknowledged. It is used by IM 0 and IM 2, the default
+    /// is a floating bus.
+    fn interrupt_acknowledge(&mut self) -> u8 {
+        0xFF
+    }
+
+    /// Reads memory without any side effect, for debuggers and state dumps.
+    fn peek(&self, address: u16) -> u8;
+}
@@ -0,0 +1,1733 @@
+use std::{cell::Cell, fmt, rc::Rc};
+
+use tracing::trace;
+
+use crate::{Bus, InternalState};
+
+// static constexpr byte S_FLAG = 0x80;
+// static constexpr byte Z_FLAG = 0x40;
+// static constexpr byte Y_FLAG = 0x20;
+// static constexpr byte H_FLAG = 0x10;
+// static constexpr byte X_FLAG = 0x08;
+// static constexpr byte V_FLAG = 0x04;
+// static constexpr byte P_FLAG = V_FLAG;
+// static constexpr byte N_FLAG = 0x02;
+// static constexpr byte C_FLAG = 0x01;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Flag {
+    S = 0x80, // Sign
+    Z = 0x40, // Zero
+    Y = 0x20, // Undocumented, copy of bit 5
+    H = 0x10, // Half Carry
+    X = 0x08, // Undocumented, copy of bit 3
+    P = 0x04, // Parity/Overflow
+    N = 0x02, // Add/Subtract
+    C = 0x01, // Carry
+}
+
+/// The Z80 /INT pin. It is open-drain, so it stays asserted while any of the
+/// devices connected to it is holding it.
+#[derive(Clone, Default)]
+pub struct InterruptLine {
+    asserted: Rc<Cell<u32>>,
+    sources: Rc<Cell<u32>>,
+    mask: u32,
+}
+
+impl InterruptLine {
+    /// Connects a new device to the line.
+    pub fn connect(&self) -> InterruptLine {
+        let index = self.sources.get();
+        assert!(
+            index < 32,
+            "Too many devices connected to the interrupt line"
+        );
+        self.sources.set(index + 1);
+
+        InterruptLine {
+            asserted: self.asserted.clone(),
+            sources: self.sources.clone(),
+            mask: 1 << index,
+        }
+    }
+
+    pub fn set(&self, active: bool) {
+        if active {
+            self.asserted.set(self.asserted.get() | self.mask);
+        } else {
+            self.asserted.set(self.asserted.get() & !self.mask);
+        }
+    }
+
+    pub fn is_asserted(&self) -> bool {
+        self.asserted.get() != 0
+    }
+}
+
+// Which register stands in for HL, selected by the DD and FD prefixes
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum IndexMode {
+    HL,
+    IX,
+    IY,
+}
+
+pub struct Z80<B: Bus> {
+    // 8-bit registers
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+
+    // Alternate registers
+    pub a_alt: u8,
+    pub f_alt: u8,
+    pub b_alt: u8,
+    pub c_alt: u8,
+    pub d_alt: u8,
+    pub e_alt: u8,
+    pub h_alt: u8,
+    pub l_alt: u8,
+
+    // 16-bit registers
+    pub sp: u16,
+    pub pc: u16,
+    pub ix: u16,
+    pub iy: u16,
+
+    // Interrupt vector and memory refresh registers
+    pub i: u8,
+    pub r: u8,
+
+    // Internal MEMPTR register, leaks into the X/Y flags of BIT n, (HL)
+    pub wz: u16,
+
+    // Internal Q latch, holds F when the last instruction changed the flags
+    // and 0 otherwise. SCF and CCF take their X/Y flags from (Q ^ F) | A.
+    pub q: u8,
+    flags_changed: bool,
+
+    // Interrupt flip-flops
+    pub iff1: bool,
+    pub iff2: bool,
+
+    // Interrupt mode
+    pub im: u8,
+    interrupt_line: InterruptLine,
+    nmi_request: bool,
+    ei_delay: bool,
+
+    // Halted?
+    pub halted: bool,
+
+    // Memory and I/O
+    pub bus: B,
+
+    // Timing
+    pub m1_wait_states: u32,
+    // T-states run since the start, including wait states
+    cycles: u64,
+    t_states: u32,
+
+    // Debug options
+    // Panics once this many T-states have run
+    pub max_cycles: Option<u64>,
+    pub track_flags: bool,
+    last_f: u8,
+}
+
+impl<B: Bus> fmt::Display for Z80<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = format!(
+            "S: {} Z: {} Y: {} H: {} X: {} P/V: {} N: {} C: {}",
+            if self.f & 0b1000_0000 != 0 { "1" } else { "0" },
+            if self.f & 0b0100_0000 != 0 { "1" } else { "0" },
+            if self.f & 0b0010_0000 != 0 { "1" } else { "0" },
+            if self.f & 0b0001_0000 != 0 { "1" } else { "0" },
+            if self.f & 0b0000_1000 != 0 { "1" } else { "0" },
+            if self.f & 0b0000_0100 != 0 { "1" } else { "0" },
+            if self.f & 0b0000_0010 != 0 { "1" } else { "0" },
+            if self.f & 0b0000_0001 != 0 { "1" } else { "0" },
+        );
+        write!(
+            f,
+            "#{:04X} - A: #{:02X} B: #{:02X} C: #{:02X} D: #{:02X} E: #{:02X} F: #{:02X} H: #{:02X} L: #{:02X} - {}",
+            self.pc, self.a, self.b, self.c, self.d, self.e, self.f, self.h, self.l, flags
+        )
+    }
+}
+
+impl<B: Bus> Z80<B> {
+    pub fn new(bus: B) -> Self {
+        Z80 {
+            a: 0xff,
+            f: 0xff,
+            b: 0xff,
+            c: 0xff,
+            d: 0xff,
+            e: 0xff,
+            h: 0xff,
+            l: 0xff,
+            a_alt: 0xff,
+            f_alt: 0xff,
+            b_alt: 0xff,
+            c_alt: 0xff,
+            d_alt: 0xff,
+            e_alt: 0xff,
+            h_alt: 0xff,
+            l_alt: 0xff,
+            sp: 0xFFFF,
+            pc: 0,
+            ix: 0xFFFF,
+            iy: 0xFFFF,
+            i: 0,
+            r: 0,
+            wz: 0,
+            q: 0,
+            flags_changed: false,
+            iff1: false,
+            iff2: false,
+            im: 0,
+            interrupt_line: InterruptLine::default(),
+            nmi_request: false,
+            ei_delay: false,
+            bus,
+            halted: false,
+            m1_wait_states: 0,
+            cycles: 0,
+            t_states: 0,
+            max_cycles: None,
+            track_flags: false,
+            last_f: 0,
+        }
+    }
+
+    /// Puts the CPU in the same state as at power on. Real hardware leaves most
+    /// registers undefined, we follow openMSX and set them all to 0xFF.
+    pub fn reset(&mut self) {
+        self.a = 0xff;
+        self.f = 0xff;
+        self.b = 0xff;
+        self.c = 0xff;
+        self.d = 0xff;
+        self.e = 0xff;
+        self.h = 0xff;
+        self.l = 0xff;
+        self.a_alt = 0xff;
+        self.f_alt = 0xff;
+        self.b_alt = 0xff;
+        self.c_alt = 0xff;
+        self.d_alt = 0xff;
+        self.e_alt = 0xff;
+        self.h_alt = 0xff;
+        self.l_alt = 0xff;
+        self.sp = 0xFFFF;
+        self.pc = 0;
+        self.ix = 0xFFFF;
+        self.iy = 0xFFFF;
+        self.i = 0;
+        self.r = 0;
+        self.wz = 0;
+        self.q = 0;
+        self.iff1 = false;
+        self.iff2 = false;
+        self.im = 0;
+        self.nmi_request = false;
+        self.ei_delay = false;
+        self.halted = false;
+    }
+
+    pub fn get_internal_state(&self) -> InternalState {
+        InternalState {
+            a: self.a,
+            f: self.f,
+            b: self.b,
+            c: self.c,
+            d: self.d,
+            e: self.e,
+            h: self.h,
+            l: self.l,
+            i: self.i,
+            r: self.r,
+            af_alt: (self.a_alt as u16) << 8 | self.f_alt as u16,
+            bc_alt: (self.b_alt as u16) << 8 | self.c_alt as u16,
+            de_alt: (self.d_alt as u16) << 8 | self.e_alt as u16,
+            hl_alt: (self.h_alt as u16) << 8 | self.l_alt as u16,
+            sp: self.sp,
+            pc: self.pc,
+            hl: self.get_hl(),
+            hl_contents: self.bus.peek(self.get_hl()),
+            opcode: self.bus.peek(self.pc),
+        }
+    }
+
+    /// Returns a connection to the /INT line for a device to drive.
+    pub fn interrupt_line(&self) -> InterruptLine {
+        self.interrupt_line.connect()
+    }
+
+    /// Triggers a non-maskable interrupt. /NMI is edge triggered, so this is
+    /// serviced once before the next instruction.
+    pub fn request_nmi(&mut self) {
+        self.nmi_request = true;
+    }
+
+    /// Executes a single instruction and returns the number of T-states it took.
+    pub fn execute_cycle(&mut self) -> u32 {
+        // Check if we reached max_cycles
+        if let Some(max_cycles) = self.max_cycles {
+            if self.cycles >= max_cycles {
+                panic!("Reached {} T-states", max_cycles);
+            }
+        }
+
+        self.t_states = 0;
+        self.flags_changed = false;
+
+        // Interrupts are not accepted right after EI
+        let ei_delay = std::mem::take(&mut self.ei_delay);
+
+        if self.nmi_request {
+            self.nmi_request = false;
+            self.accept_nmi();
+        } else if self.iff1 && !ei_delay && self.interrupt_line.is_asserted() {
+            self.accept_interrupt();
+        } else if self.halted {
+            // A halted CPU keeps executing NOPs
+            self.increment_refresh();
+            self.add_t_states(4 + self.m1_wait_states);
+        } else {
+            // Fetch and decode the next instruction
+            let opcode = self.fetch_opcode();
+
+            // Execute the instruction
+            self.execute_opcode(opcode, IndexMode::HL);
+
+            if self.track_flags && self.f != self.last_f {
+                trace!(
+                    " *** Flags updated -> before = {:08b}, after = {:08b} Z={}\n",
+                    self.last_f,
+                    self.f,
+                    self.check_flag(Flag::Z)
+                );
+                self.last_f = self.f;
+            }
+        }
+
+        // Loading F whole with POP AF or EX AF, AF' doesn't go through the
+        // flag logic and clears Q like any other instruction
+        self.q = if self.flags_changed { self.f } else { 0 };
+
+        self.cycles += self.t_states as u64;
+        self.t_states
+    }
+
+    fn accept_nmi(&mut self) {
+        trace!("NMI at 0x{:04X}", self.pc);
+        self.halted = false;
+        self.increment_refresh();
+
+        // IFF2 keeps the previous state so RETN can restore it
+        self.iff1 = false;
+
+        self.add_t_states(11 + self.m1_wait_states);
+        self.rst(0x0066);
+    }
+
+    fn accept_interrupt(&mut self) {
+        trace!("Interrupt (IM {}) at 0x{:04X}", self.im, self.pc);
+        self.halted = false;
+        self.iff1 = false;
+        self.iff2 = false;
+        self.increment_refresh();
+
+        // The acknowledge cycle is an M1 cycle with two extra wait states
+        self.add_t_states(2 + self.m1_wait_states);
+        let data = self.bus.interrupt_acknowledge();
+
+        match self.im {
+            0 => {
+                // The device puts an instruction on the data bus, in practice
+                // always a single byte one like RST
+                self.execute_opcode(data, IndexMode::HL);
+            }
+            1 => {
+                self.add_t_states(11);
+                self.rst(0x0038);
+            }
+            _ => {
+                let vector = (self.i as u16) << 8 | data as u16;
+                let address = self.read_word(vector);
+                self.add_t_states(17);
+                self.rst(address);
+            }
+        }
+    }
+
+    // Opcodes are decoded by splitting them into the fields described in
+    // "Decoding Z80 Opcodes" (http://www.z80.info/decoding.htm):
+    //
+    //   x = bits 7-6, y = bits 5-3, z = bits 2-0, p = bits 5-4, q = bit 3
+    //
+    // DD and FD don't have their own instruction set, they run the unprefixed
+    // one with HL, H and L and (HL) replaced by IX/IY, IXH/IXL and (IX+d).
+    fn execute_opcode(&mut self, opcode: u8, mode: IndexMode) {
+        let t_states = match mode {
+            IndexMode::HL => MAIN_T_STATES[opcode as usize],
+            _ => INDEX_T_STATES[opcode as usize],
+        };
+        self.add_t_states(t_states as u32);
+
+        let x = opcode >> 6;
+        let y = (opcode >> 3) & 0x07;
+        let z = opcode & 0x07;
+        let p = y >> 1;
+        let q = y & 0x01;
+
+        match (x, z) {
+            (0, 0) => match y {
+                0 => self.nop(),
+                1 => {
+                    // EX AF, AF'
+                    std::mem::swap(&mut self.a, &mut self.a_alt);
+                    std::mem::swap(&mut self.f, &mut self.f_alt);
+                }
+                2 => {
+                    // DJNZ e
+                    let offset = self.fetch_byte() as i8;
+                    self.b = self.b.wrapping_sub(1);
+                    if self.b != 0 {
+                        self.jump_relative(offset);
+                        self.add_t_states(5);
+                    }
+                }
+                3 => {
+                    // JR e
+                    let offset = self.fetch_byte() as i8;
+                    self.jump_relative(offset);
+                }
+                _ => {
+                    // JR cc, e
+                    let offset = self.fetch_byte() as i8;
+                    if self.condition(y - 4) {
+                        self.jump_relative(offset);
+                        self.add_t_states(5);
+                    }
+                }
+            },
+            (0, 1) => {
+                if q == 0 {
+                    // LD rr, nn
+                    let value = self.fetch_word();
+                    self.set_register_pair_by_index(p, mode, value);
+                } else {
+                    // ADD HL, rr
+                    let value = self.get_register_pair_by_index(p, mode);
+                    let result = self.add16(self.get_index(mode), value);
+                    self.set_index(mode, result);
+                }
+            }
+            (0, 2) => match (q, p) {
+                (0, 0) => {
+                    // LD (BC), A
+                    self.write_byte(self.get_bc(), self.a);
+                    self.wz = (self.a as u16) << 8 | (self.get_bc().wrapping_add(1) & 0xFF);
+                }
+                (0, 1) => {
+                    // LD (DE), A
+                    self.ld_de_a();
+                }
+                (0, 2) => {
+                    // LD (nn), HL
+                    let address = self.fetch_word();
+                    self.write_word(address, self.get_index(mode));
+                    self.wz = address.wrapping_add(1);
+                }
+                (0, _) => {
+                    // LD (nn), A
+                    let address = self.fetch_word();
+                    self.write_byte(address, self.a);
+                    self.wz = (self.a as u16) << 8 | (address.wrapping_add(1) & 0xFF);
+                }
+                (_, 0) => {
+                    // LD A, (BC)
+                    self.ld_a_bc();
+                }
+                (_, 1) => {
+                    // LD A, (DE)
+                    self.ld_a_de();
+                }
+                (_, 2) => {
+                    // LD HL, (nn)
+                    let address = self.fetch_word();
+                    let value = self.read_word(address);
+                    self.set_index(mode, value);
+                    self.wz = address.wrapping_add(1);
+                }
+                (_, _) => {
+                    // LD A, (nn)
+                    let address = self.fetch_word();
+                    self.a = self.read_byte(address);
+                    self.wz = address.wrapping_add(1);
+                }
+            },
+            (0, 3) => {
+                // INC rr / DEC rr
+                let value = self.get_register_pair_by_index(p, mode);
+                let result = match q {
+                    0 => value.wrapping_add(1),
+                    _ => value.wrapping_sub(1),
+                };
+                self.set_register_pair_by_index(p, mode, result);
+            }
+            (0, 4) | (0, 5) => {
+                // INC r / DEC r
+                let operation = match z {
+                    4 => Self::inc,
+                    _ => Self::dec,
+                };
+                if y == 6 {
+                    let address = self.memory_operand(mode);
+                    let value = self.read_byte(address);
+                    let result = operation(self, value);
+                    self.write_byte(address, result);
+                } else {
+                    let value = self.get_register_by_index(y, mode);
+                    let result = operation(self, value);
+                    self.set_register_by_index(y, mode, result);
+                }
+            }
+            (0, 6) => {
+                // LD r, n
+                if y == 6 {
+                    let address = self.memory_operand(mode);
+                    let value = self.fetch_byte();
+                    self.write_byte(address, value);
+                } else {
+                    let value = self.fetch_byte();
+                    self.set_register_by_index(y, mode, value);
+                }
+            }
+            (0, 7) => match y {
+                0 => {
+                    // RLCA
+                    let carry = self.a & 0x80 != 0;
+                    self.a = self.a.rotate_left(1);
+                    self.set_accumulator_rotate_flags(carry);
+                }
+                1 => {
+                    // RRCA
+                    let carry = self.a & 0x01 != 0;
+                    self.a = self.a.rotate_right(1);
+                    self.set_accumulator_rotate_flags(carry);
+                }
+                2 => {
+                    // RLA
+                    let carry = self.a & 0x80 != 0;
+                    self.a = (self.a << 1) | self.get_flag(Flag::C) as u8;
+                    self.set_accumulator_rotate_flags(carry);
+                }
+                3 => {
+                    // RRA
+                    let carry = self.a & 0x01 != 0;
+                    self.a = (self.a >> 1) | ((self.get_flag(Flag::C) as u8) << 7);
+                    self.set_accumulator_rotate_flags(carry);
+                }
+                4 => {
+                    // DAA
+                    self.daa();
+                }
+                5 => {
+                    // CPL
+                    self.a = !self.a;
+                    self.set_flag(Flag::N, true);
+                    self.set_flag(Flag::H, true);
+                    self.set_undocumented_flags(self.a);
+                }
+                6 => {
+                    // SCF
+                    self.set_flag(Flag::N, false);
+                    self.set_flag(Flag::H, false);
+                    self.set_flag(Flag::C, true);
+                    self.set_undocumented_flags((self.q ^ self.f) | self.a);
+                }
+                _ => {
+                    // CCF
+                    let carry = self.get_flag(Flag::C);
+                    self.set_flag(Flag::N, false);
+                    self.set_flag(Flag::H, carry);
+                    self.set_flag(Flag::C, !carry);
+                    self.set_undocumented_flags((self.q ^ self.f) | self.a);
+                }
+            },
+            (1, 6) if y == 6 => {
+                // HALT
+                self.halted = true;
+            }
+            (1, _) => {
+                // LD r, r'. When one side is (IX+d) the other one keeps
+                // using H and L instead of IXH and IXL.
+                if z == 6 {
+                    let address = self.memory_operand(mode);
+                    let value = self.read_byte(address);
+                    self.set_register_by_index(y, IndexMode::HL, value);
+                } else if y == 6 {
+                    let address = self.memory_operand(mode);
+                    let value = self.get_register_by_index(z, IndexMode::HL);
+                    self.write_byte(address, value);
+                } else {
+                    let value = self.get_register_by_index(z, mode);
+                    self.set_register_by_index(y, mode, value);
+                }
+            }
+            (2, _) => {
+                // ADD/ADC/SUB/SBC/AND/XOR/OR/CP r
+                let value = self.read_operand(z, mode);
+                self.alu_by_index(y, value);
+            }
+            (3, 0) => {
+                // RET cc
+                if self.condition(y) {
+                    self.ret();
+                    self.add_t_states(6);
+                }
+            }
+            (3, 1) => match (q, p) {
+                (0, _) => {
+                    // POP rr
+                    let value = self.pop();
+                    self.set_stack_pair_by_index(p, mode, value);
+                }
+                (_, 0) => {
+                    // RET
+                    self.ret();
+                }
+                (_, 1) => {
+                    // EXX
+                    std::mem::swap(&mut self.b, &mut self.b_alt);
+                    std::mem::swap(&mut self.c, &mut self.c_alt);
+                    std::mem::swap(&mut self.d, &mut self.d_alt);
+                    std::mem::swap(&mut self.e, &mut self.e_alt);
+                    std::mem::swap(&mut self.h, &mut self.h_alt);
+                    std::mem::swap(&mut self.l, &mut self.l_alt);
+                }
+                (_, 2) => {
+                    // JP (HL)
+                    self.pc = self.get_index(mode);
+                }
+                (_, _) => {
+                    // LD SP, HL
+                    self.sp = self.get_index(mode);
+                }
+            },
+            (3, 2) => {
+                // JP cc, nn
+                let address = self.fetch_word();
+                self.wz = address;
+                if self.condition(y) {
+                    self.pc = address;
+                }
+            }
+            (3, 3) => match y {
+                0 => {
+                    // JP nn
+                    self.pc = self.fetch_word();
+                    self.wz = self.pc;
+                }
+                1 => self.execute_cb(mode),
+                2 => {
+                    // OUT (n), A
+                    let port = self.fetch_byte();
+                    self.io_write((self.a as u16) << 8 | port as u16, self.a);
+                    self.wz = (self.a as u16) << 8 | (port.wrapping_add(1) as u16);
+                }
+                3 => {
+                    // IN A, (n)
+                    let port = self.fetch_byte();
+                    self.wz = ((self.a as u16) << 8 | port as u16).wrapping_add(1);
+                    self.a = self.io_read((self.a as u16) << 8 | port as u16);
+                }
+                4 => {
+                    // EX (SP), HL
+                    let value = self.read_word(self.sp);
+                    self.write_word(self.sp, self.get_index(mode));
+                    self.set_index(mode, value);
+                    self.wz = value;
+                }
+                5 => {
+                    // EX DE, HL (not affected by DD/FD)
+                    let de = self.get_de();
+                    let hl = self.get_hl();
+                    self.set_de(hl);
+                    self.set_hl(de);
+                }
+                6 => {
+                    // DI
+                    self.iff1 = false;
+                    self.iff2 = false;
+                }
+                _ => {
+                    // EI
+                    self.iff1 = true;
+                    self.iff2 = true;
+                    self.ei_delay = true;
+                }
+            },
+            (3, 4) => {
+                // CALL cc, nn
+                let address = self.fetch_word();
+                self.wz = address;
+                if self.condition(y) {
+                    self.call(address);
+                    self.add_t_states(7);
+                }
+            }
+            (3, 5) => match (q, p) {
+                (0, _) => {
+                    // PUSH rr
+                    let value = self.get_stack_pair_by_index(p, mode);
+                    self.push(value);
+                }
+                (_, 0) => {
+                    // CALL nn
+                    let address = self.fetch_word();
+                    self.call(address);
+                }
+                (_, 1) => {
+                    // DD prefix, a later prefix overrides an earlier one
+                    let opcode = self.fetch_opcode();
+                    self.execute_opcode(opcode, IndexMode::IX);
+                }
+                (_, 2) => self.execute_ed(),
+                (_, _) => {
+                    // FD prefix
+                    let opcode = self.fetch_opcode();
+                    self.execute_opcode(opcode, IndexMode::IY);
+                }
+            },
+            (3, 6) => {
+                // ADD/ADC/SUB/SBC/AND/XOR/OR/CP n
+                let value = self.fetch_byte();
+                self.alu_by_index(y, value);
+            }
+            (3, _) => {
+                // RST p
+                self.rst((y as u16) << 3);
+            }
+            _ => unreachable!("Invalid opcode decomposition: 0x{:02X}", opcode),
+        }
+    }
+
+    fn execute_cb(&mut self, mode: IndexMode) {
+        // DDCB/FDCB opcodes have the displacement before the opcode, which is
+        // read as data rather than fetched as an M1 cycle
+        let (opcode, address) = match mode {
+            IndexMode::HL => (self.fetch_opcode(), self.get_hl()),
+            _ => {
+                let address = self.memory_operand(mode);
+                (self.fetch_byte(), address)
+            }
+        };
+
+        let x = opcode >> 6;
+        let y = (opcode >> 3) & 0x07;
+        let z = opcode & 0x07;
+
+        self.add_t_states(match (mode, x) {
+            (IndexMode::HL, _) => cb_t_states(opcode),
+            (_, 1) => 20,
+            _ => 23,
+        });
+
+        // Indexed forms always operate on (IX+d)
+        let memory = mode != IndexMode::HL || z == 6;
+        let value = match memory {
+            true => self.read_byte(address),
+            false => self.get_register_by_index(z, IndexMode::HL),
+        };
+
+        let result = match x {
+            // RLC/RRC/RL/RR/SLA/SRA/SLL/SRL r
+            0 => self.rotate_shift_by_index(y, value),
+            1 => {
+                // BIT b, r
+                self.bit(y, value);
+                if memory {
+                    // BIT b, (HL) leaks the high byte of MEMPTR into X/Y
+                    self.set_undocumented_flags((self.wz >> 8) as u8);
+                }
+                return;
+            }
+            // RES b, r
+            2 => value & !(1 << y),
+            // SET b, r
+            _ => value | (1 << y),
+        };
+
+        if memory {
+            self.write_byte(address, result);
+        }
+
+        // Undocumented: DDCB/FDCB also copy the result into a register
+        if z != 6 {
+            self.set_register_by_index(z, IndexMode::HL, result);
+        }
+    }
+
+    fn execute_ed(&mut self) {
+        let opcode = self.fetch_opcode();
+
+        self.add_t_states(ED_T_STATES[opcode as usize] as u32);
+
+        let y = (opcode >> 3) & 0x07;
+        let z = opcode & 0x07;
+        let p = y >> 1;
+        let q = y & 0x01;
+
+        match (opcode >> 6, z) {
+            (1, 0) => {
+                // IN r, (C) / IN (C) (undocumented, only affects flags)
+                let value = self.io_read(self.get_bc());
+                let carry = self.get_flag(Flag::C);
+                self.set_logic_flags(value, false);
+                self.set_flag(Flag::C, carry);
+                self.wz = self.get_bc().wrapping_add(1);
+                if y != 6 {
+                    self.set_register_by_index(y, IndexMode::HL, value);
+                }
+            }
+            (1, 1) => {
+                // OUT (C), r / OUT (C), 0 (undocumented)
+                let value = match y {
+                    6 => 0,
+                    _ => self.get_register_by_index(y, IndexMode::HL),
+                };
+                self.io_write(self.get_bc(), value);
+                self.wz = self.get_bc().wrapping_add(1);
+            }
+            (1, 2) => {
+                // SBC HL, rr / ADC HL, rr
+                let value = self.get_register_pair_by_index(p, IndexMode::HL);
+                match q {
+                    0 => self.sbc_hl(value),
+                    _ => self.adc_hl(value),
+                }
+            }
+            (1, 3) => {
+                // LD (nn), rr / LD rr, (nn)
+                let address = self.fetch_word();
+                if q == 0 {
+                    let value = self.get_register_pair_by_index(p, IndexMode::HL);
+                    self.write_word(address, value);
+                } else {
+                    let value = self.read_word(address);
+                    self.set_register_pair_by_index(p, IndexMode::HL, value);
+                }
+                self.wz = address.wrapping_add(1);
+            }
+            (1, 4) => {
+                // NEG (and its undocumented mirrors)
+                let value = self.a;
+                self.a = 0;
+                self.sub_a(value);
+            }
+            (1, 5) => {
+                // RETN / RETI (and their undocumented mirrors), all of them
+                // copy IFF2 back into IFF1
+                self.iff1 = self.iff2;
+                self.ret();
+            }
+            (1, 6) => {
+                // IM 0/1/2 (and their undocumented mirrors)
+                self.im = match y & 0x03 {
+                    2 => 1,
+                    3 => 2,
+                    _ => 0,
+                };
+            }
+            (1, 7) => match y {
+                0 => {
+                    // LD I, A
+                    self.i = self.a;
+                }
+                1 => {
+                    // LD R, A
+                    self.r = self.a;
+                }
+                2 => {
+                    // LD A, I
+                    self.a = self.i;
+                    self.set_ld_a_ir_flags();
+                }
+                3 => {
+                    // LD A, R
+                    self.a = self.r;
+                    self.set_ld_a_ir_flags();
+                }
+                4 => {
+                    // RRD
+                    let address = self.get_hl();
+                    let value = self.read_byte(address);
+                    self.write_byte(address, (self.a << 4) | (value >> 4));
+                    self.a = (self.a & 0xF0) | (value & 0x0F);
+                    self.set_rotate_digit_flags();
+                    self.wz = address.wrapping_add(1);
+                }
+                5 => {
+                    // RLD
+                    let address = self.get_hl();
+                    let value = self.read_byte(address);
+                    self.write_byte(address, (value << 4) | (self.a & 0x0F));
+                    self.a = (self.a & 0xF0) | (value >> 4);
+                    self.set_rotate_digit_flags();
+                    self.wz = address.wrapping_add(1);
+                }
+                _ => self.nop(),
+            },
+            (2, 0..=3) if y >= 4 => {
+                // LDI/LDD/LDIR/LDDR, CPI/CPD/CPIR/CPDR, INI/IND/INIR/INDR and
+                // OUTI/OUTD/OTIR/OTDR. Bit 3 selects the direction and bit 4
+                // makes them repeat.
+                let step = match q {
+                    0 => 1,
+                    _ => -1,
+                };
+                let repeat = p == 3;
+                match z {
+                    0 => {
+                        self.block_transfer(step);
+                        if repeat && self.repeat_if(self.get_bc() != 0) {
+                            self.wz = self.pc.wrapping_add(1);
+                        }
+                    }
+                    1 => {
+                        self.block_compare(step);
+                        let condition = self.get_bc() != 0 && !self.get_flag(Flag::Z);
+                        if repeat && self.repeat_if(condition) {
+                            self.wz = self.pc.wrapping_add(1);
+                        }
+                    }
+                    2 => {
+                        self.block_in(step);
+                        if repeat {
+                            self.repeat_if(self.b != 0);
+                        }
+                    }
+                    _ => {
+                        self.block_out(step);
+                        if repeat {
+                            self.repeat_if(self.b != 0);
+                        }
+                    }
+                }
+            }
+            _ => {
+                // Every other ED opcode behaves as a two byte NOP
+                trace!("NONI 0xED 0x{:02X}", opcode);
+            }
+        }
+    }
+
+    fn add_t_states(&mut self, t_states: u32) {
+        self.t_states += t_states;
+    }
+
+    // Opcode fetch (M1) cycle, MSX machines insert a wait state in each one
+    fn fetch_opcode(&mut self) -> u8 {
+        self.increment_refresh();
+        self.add_t_states(self.m1_wait_states);
+        let opcode = self.bus.fetch_opcode(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        opcode
+    }
+
+    /// Every M1 cycle, prefixes included, refreshes a DRAM row and bumps the
+    /// lower 7 bits of R. Bit 7 only changes through LD R,A.
+    fn increment_refresh(&mut self) {
+        self.r = (self.r & 0x80) | (self.r.wrapping_add(1) & 0x7F);
+    }
+
+    fn fetch_byte(&mut self) -> u8 {
+        let value = self.read_byte(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        value
+    }
+
+    fn fetch_word(&mut self) -> u16 {
+        let value = self.read_word(self.pc);
+        self.pc = self.pc.wrapping_add(2);
+        value
+    }
+
+    // Address of the (HL) operand. With DD/FD it is (IX+d)/(IY+d) and the
+    // displacement is fetched here.
+    fn memory_operand(&mut self, mode: IndexMode) -> u16 {
+        match mode {
+            IndexMode::HL => self.get_hl(),
+            _ => {
+                let displacement = self.fetch_byte() as i8 as u16;
+                let address = self.get_index(mode).wrapping_add(displacement);
+                self.wz = address;
+                address
+            }
+        }
+    }
+
+    fn read_operand(&mut self, index: u8, mode: IndexMode) -> u8 {
+        match index {
+            6 => {
+                let address = self.memory_operand(mode);
+                self.read_byte(address)
+            }
+            _ => self.get_register_by_index(index, mode),
+        }
+    }
+
+    // HL, IX or IY depending on the prefix
+    fn get_index(&self, mode: IndexMode) -> u16 {
+        match mode {
+            IndexMode::HL => self.get_hl(),
+            IndexMode::IX => self.ix,
+            IndexMode::IY => self.iy,
+        }
+    }
+
+    fn set_index(&mut self, mode: IndexMode, value: u16) {
+        match mode {
+            IndexMode::HL => self.set_hl(value),
+            IndexMode::IX => self.ix = value,
+            IndexMode::IY => self.iy = value,
+        }
+    }
+
+    fn jump_relative(&mut self, offset: i8) {
+        self.pc = self.pc.wrapping_add(offset as u16);
+        self.wz = self.pc;
+    }
+
+    fn repeat_if(&mut self, condition: bool) -> bool {
+        // Block instructions repeat by re-executing themselves
+        if condition {
+            self.pc = self.pc.wrapping_sub(2);
+            self.add_t_states(5);
+        }
+        condition
+    }
+
+    fn condition(&self, index: u8) -> bool {
+        match index {
+            0 => !self.get_flag(Flag::Z), // NZ
+            1 => self.get_flag(Flag::Z),  // Z
+            2 => !self.get_flag(Flag::C), // NC
+            3 => self.get_flag(Flag::C),  // C
+            4 => !self.get_flag(Flag::P), // PO
+            5 => self.get_flag(Flag::P),  // PE
+            6 => !self.get_flag(Flag::S), // P
+            7 => self.get_flag(Flag::S),  // M
+            _ => panic!("Invalid condition index: {}", index),
+        }
+    }
+
+    fn io_read(&mut self, port: u16) -> u8 {
+        self.bus.input(port)
+    }
+
+    fn io_write(&mut self, port: u16, value: u8) {
+        self.bus.output(port, value);
+    }
+
+    fn alu_by_index(&mut self, index: u8, value: u8) {
+        match index {
+            0 => self.add_a(value),
+            1 => self.adc_a(value),
+            2 => self.sub_a(value),
+            3 => self.sbc_a(value),
+            4 => self.and_a(value),
+            5 => self.xor_a(value),
+            6 => self.or_a(value),
+            7 => self.cp(value),
+            _ => panic!("Invalid ALU index: {}", index),
+        }
+    }
+
+    fn rotate_shift_by_index(&mut self, index: u8, value: u8) -> u8 {
+        let carry_in = self.get_flag(Flag::C) as u8;
+        let (result, carry) = match index {
+            0 => (value.rotate_left(1), value & 0x80 != 0), // RLC
+            1 => (value.rotate_right(1), value & 0x01 != 0), // RRC
+            2 => ((value << 1) | carry_in, value & 0x80 != 0), // RL
+            3 => ((value >> 1) | (carry_in << 7), value & 0x01 != 0), // RR
+            4 => (value << 1, value & 0x80 != 0),           // SLA
+            5 => ((value >> 1) | (value & 0x80), value & 0x01 != 0), // SRA
+            6 => ((value << 1) | 0x01, value & 0x80 != 0),  // SLL (undocumented)
+            7 => (value >> 1, value & 0x01 != 0),           // SRL
+            _ => panic!("Invalid rotate/shift index: {}", index),
+        };
+
+        self.set_logic_flags(result, false);
+        self.set_flag(Flag::C, carry);
+        result
+    }
+
+    fn add_a(&mut self, value: u8) {
+        self.add8(value, false);
+    }
+
+    fn adc_a(&mut self, value: u8) {
+        self.add8(value, self.get_flag(Flag::C));
+    }
+
+    fn add8(&mut self, value: u8, carry: bool) {
+        let a = self.a;
+        let carry = carry as u8;
+        let result = a.wrapping_add(value).wrapping_add(carry);
+
+        self.set_flag(Flag::S, result & 0x80 != 0);
+        self.set_flag(Flag::Z, result == 0);
+        self.set_flag(Flag::H, (a & 0x0F) + (value & 0x0F) + carry > 0x0F);
+        self.set_flag(Flag::P, ((a ^ result) & !(a ^ value)) & 0x80 != 0);
+        self.set_flag(Flag::N, false);
+        self.set_flag(Flag::C, (a as u16) + (value as u16) + (carry as u16) > 0xFF);
+        self.set_undocumented_flags(result);
+
+        self.a = result;
+    }
+
+    fn sub_a(&mut self, value: u8) {
+        self.a = self.sub8(value, false);
+    }
+
+    fn sbc_a(&mut self, value: u8) {
+        self.a = self.sub8(value, self.get_flag(Flag::C));
+    }
+
+    fn cp(&mut self, value: u8) {
+        self.sub8(value, false);
+        // CP takes X and Y from the operand rather than the result
+        self.set_undocumented_flags(value);
+    }
+
+    fn sub8(&mut self, value: u8, carry: bool) -> u8 {
+        let a = self.a;
+        let carry = carry as u8;
+        let result = a.wrapping_sub(value).wrapping_sub(carry);
+
+        self.set_flag(Flag::S, result & 0x80 != 0);
+        self.set_flag(Flag::Z, result == 0);
+        self.set_flag(Flag::H, (a & 0x0F) < (value & 0x0F) + carry);
+        self.set_flag(Flag::P, overflow_sub(a, value, result));
+        self.set_flag(Flag::N, true);
+        self.set_flag(Flag::C, (a as u16) < (value as u16) + (carry as u16));
+        self.set_undocumented_flags(result);
+
+        result
+    }
+
+    fn and_a(&mut self, value: u8) {
+        self.a &= value;
+        self.set_logic_flags(self.a, true);
+    }
+
+    fn or_a(&mut self, value: u8) {
+        self.a |= value;
+        self.set_logic_flags(self.a, false);
+    }
+
+    fn xor_a(&mut self, value: u8) {
+        self.a ^= value;
+        self.set_logic_flags(self.a, false);
+    }
+
+    fn inc(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_add(1);
+        self.set_inc_flags(result);
+        result
+    }
+
+    fn dec(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_sub(1);
+        self.set_dec_flags(result);
+        result
+    }
+
+    fn add16(&mut self, a: u16, b: u16) -> u16 {
+        let (result, carry) = a.overflowing_add(b);
+
+        self.set_flag(Flag::H, (a & 0x0FFF) + (b & 0x0FFF) > 0x0FFF);
+        self.set_flag(Flag::N, false);
+        self.set_flag(Flag::C, carry);
+        self.set_undocumented_flags((result >> 8) as u8);
+        self.wz = a.wrapping_add(1);
+
+        result
+    }
+
+    fn adc_hl(&mut self, value: u16) {
+        let hl = self.get_hl();
+        let carry = self.get_flag(Flag::C) as u32;
+        let result = hl as u32 + value as u32 + carry;
+        let result16 = result as u16;
+
+        self.set_flag(Flag::S, result16 & 0x8000 != 0);
+        self.set_flag(Flag::Z, result16 == 0);
+        self.set_flag(
+            Flag::H,
+            (hl & 0x0FFF) as u32 + (value & 0x0FFF) as u32 + carry > 0x0FFF,
+        );
+        self.set_flag(Flag::P, ((hl ^ result16) & !(hl ^ value)) & 0x8000 != 0);
+        self.set_flag(Flag::N, false);
+        self.set_flag(Flag::C, result > 0xFFFF);
+        self.set_undocumented_flags((result16 >> 8) as u8);
+        self.wz = hl.wrapping_add(1);
+
+        self.set_hl(result16);
+    }
+
+    fn sbc_hl(&mut self, value: u16) {
+        let hl = self.get_hl();
+        let carry = self.get_flag(Flag::C) as u32;
+        let result16 = hl.wrapping_sub(value).wrapping_sub(carry as u16);
+
+        self.set_flag(Flag::S, result16 & 0x8000 != 0);
+        self.set_flag(Flag::Z, result16 == 0);
+        self.set_flag(
+            Flag::H,
+            ((hl & 0x0FFF) as u32) < (value & 0x0FFF) as u32 + carry,
+        );
+        self.set_flag(Flag::P, ((hl ^ value) & (hl ^ result16)) & 0x8000 != 0);
+        self.set_flag(Flag::N, true);
+        self.set_flag(Flag::C, (hl as u32) < value as u32 + carry);
+        self.set_undocumented_flags((result16 >> 8) as u8);
+        self.wz = hl.wrapping_add(1);
+
+        self.set_hl(result16);
+    }
+
+    fn daa(&mut self) {
+        let a = self.a;
+        let mut correction = 0;
+        let mut carry = self.get_flag(Flag::C);
+
+        if self.get_flag(Flag::H) || (a & 0x0F) > 0x09 {
+            correction |= 0x06;
+        }
+        if carry || a > 0x99 {
+            correction |= 0x60;
+            carry = true;
+        }
+
+        let result = if self.get_flag(Flag::N) {
+            self.set_flag(Flag::H, self.get_flag(Flag::H) && (a & 0x0F) < 0x06);
+            a.wrapping_sub(correction)
+        } else {
+            self.set_flag(Flag::H, (a & 0x0F) > 0x09);
+            a.wrapping_add(correction)
+        };
+
+        self.set_flag(Flag::S, result & 0x80 != 0);
+        self.set_flag(Flag::Z, result == 0);
+        self.set_flag(Flag::P, parity(result));
+        self.set_flag(Flag::C, carry);
+        self.set_undocumented_flags(result);
+
+        self.a = result;
+    }
+
+    fn bit(&mut self, bit: u8, value: u8) {
+        let bit_value = value & (1 << bit);
+
+        self.set_flag(Flag::S, bit_value & 0x80 != 0);
+        self.set_flag(Flag::Z, bit_value == 0);
+        self.set_flag(Flag::H, true);
+        self.set_flag(Flag::P, bit_value == 0); // P/V flag is set to the inverse of the Z flag
+        self.set_flag(Flag::N, false);
+        self.set_undocumented_flags(value);
+    }
+
+    // Helper function to set the undocumented X and Y flags from bits 3 and 5
+    fn set_undocumented_flags(&mut self, value: u8) {
+        self.set_flag(Flag::X, value & 0x08 != 0);
+        self.set_flag(Flag::Y, value & 0x20 != 0);
+    }
+
+    // Helper function to set flags for AND/OR/XOR and the rotate/shift family
+    fn set_logic_flags(&mut self, value: u8, half_carry: bool) {
+        self.set_flag(Flag::S, value & 0x80 != 0);
+        self.set_flag(Flag::Z, value == 0);
+        self.set_flag(Flag::H, half_carry);
+        self.set_flag(Flag::P, parity(value));
+        self.set_flag(Flag::N, false);
+        self.set_flag(Flag::C, false);
+        self.set_undocumented_flags(value);
+    }
+
+    // Helper function to set flags for RLCA/RRCA/RLA/RRA
+    fn set_accumulator_rotate_flags(&mut self, carry: bool) {
+        self.set_flag(Flag::H, false);
+        self.set_flag(Flag::N, false);
+        self.set_flag(Flag::C, carry);
+        self.set_undocumented_flags(self.a);
+    }
+
+    // Helper function to set flags for RLD and RRD
+    fn set_rotate_digit_flags(&mut self) {
+        let carry = self.get_flag(Flag::C);
+        self.set_logic_flags(self.a, false);
+        self.set_flag(Flag::C, carry);
+    }
+
+    // Helper function to set flags for LD A, I and LD A, R
+    fn set_ld_a_ir_flags(&mut self) {
+        self.set_flag(Flag::S, self.a & 0x80 != 0);
+        self.set_flag(Flag::Z, self.a == 0);
+        self.set_flag(Flag::H, false);
+        self.set_flag(Flag::P, self.iff2);
+        self.set_flag(Flag::N, false);
+        self.set_undocumented_flags(self.a);
+    }
+
+    // Helper function to set flags for INC
+    fn set_inc_flags(&mut self, value: u8) {
+        self.set_flag(Flag::S, value & 0x80 != 0);
+        self.set_flag(Flag::Z, value == 0);
+        self.set_flag(Flag::H, (value & 0x0F) == 0x00);
+        self.set_flag(Flag::P, value == 0x80);
+        self.set_flag(Flag::N, false);
+        self.set_undocumented_flags(value);
+    }
+
+    // Helper function to set flags for DEC
+    fn set_dec_flags(&mut self, value: u8) {
+        self.set_flag(Flag::S, value & 0x80 != 0);
+        self.set_flag(Flag::Z, value == 0);
+        self.set_flag(Flag::H, (value & 0x0F) == 0x0F);
+        self.set_flag(Flag::P, value == 0x7F);
+        self.set_flag(Flag::N, true);
+        self.set_undocumented_flags(value);
+    }
+
+    // Helper function to set flags for INI/IND/OUTI/OUTD, `k` is the value
+    // added to the transferred byte by the undocumented flag logic
+    fn set_block_io_flags(&mut self, value: u8, k: u16) {
+        self.set_flag(Flag::S, self.b & 0x80 != 0);
+        self.set_flag(Flag::Z, self.b == 0);
+        self.set_flag(Flag::H, k > 0xFF);
+        self.set_flag(Flag::P, parity((k as u8 & 0x07) ^ self.b));
+        self.set_flag(Flag::N, value & 0x80 != 0);
+        self.set_flag(Flag::C, k > 0xFF);
+        self.set_undocumented_flags(self.b);
+    }
+
+    pub fn set_flag(&mut self, flag: Flag, value: bool) {
+        self.flags_changed = true;
+        if value {
+            self.f |= flag as u8;
+        } else {
+            self.f &= !(flag as u8);
+        }
+    }
+
+    pub fn get_flag(&self, flag: Flag) -> bool {
+        self.f & (flag as u8) != 0
+    }
+
+    pub fn check_flag(&self, flag: Flag) -> bool {
+        self.get_flag(flag)
+    }
+
+    fn read_byte(&mut self, address: u16) -> u8 {
+        self.bus.read(address)
+    }
+
+    fn read_word(&mut self, address: u16) -> u16 {
+        let low = self.bus.read(address) as u16;
+        let high = self.bus.read(address.wrapping_add(1)) as u16;
+        (high << 8) | low
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        self.bus.write(address, value);
+    }
+
+    fn write_word(&mut self, address: u16, value: u16) {
+        self.bus.write(address, value as u8);
+        self.bus.write(address.wrapping_add(1), (value >> 8) as u8);
+    }
+
+    // B, C, D, E, H, L, A. Index 6 is the (HL) memory operand, which is
+    // handled by `memory_operand`. DD/FD replace H and L by IXH/IXL or IYH/IYL.
+    fn get_register_by_index(&self, index: u8, mode: IndexMode) -> u8 {
+        match index {
+            0 => self.b,
+            1 => self.c,
+            2 => self.d,
+            3 => self.e,
+            4 => (self.get_index(mode) >> 8) as u8,
+            5 => self.get_index(mode) as u8,
+            7 => self.a,
+            _ => panic!("Invalid register index: {}", index),
+        }
+    }
+
+    fn set_register_by_index(&mut self, index: u8, mode: IndexMode, value: u8) {
+        match index {
+            0 => self.b = value,
+            1 => self.c = value,
+            2 => self.d = value,
+            3 => self.e = value,
+            4 => {
+                let low = self.get_index(mode) & 0x00FF;
+                self.set_index(mode, (value as u16) << 8 | low);
+            }
+            5 => {
+                let high = self.get_index(mode) & 0xFF00;
+                self.set_index(mode, high | value as u16);
+            }
+            7 => self.a = value,
+            _ => panic!("Invalid register index: {}", index),
+        }
+    }
+
+    // BC, DE, HL, SP
+    fn get_register_pair_by_index(&self, index: u8, mode: IndexMode) -> u16 {
+        match index {
+            0 => self.get_bc(),
+            1 => self.get_de(),
+            2 => self.get_index(mode),
+            3 => self.sp,
+            _ => panic!("Invalid register pair index: {}", index),
+        }
+    }
+
+    fn set_register_pair_by_index(&mut self, index: u8, mode: IndexMode, value: u16) {
+        match index {
+            0 => self.set_bc(value),
+            1 => self.set_de(value),
+            2 => self.set_index(mode, value),
+            3 => self.sp = value,
+            _ => panic!("Invalid register pair index: {}", index),
+        }
+    }
+
+    // BC, DE, HL, AF (as used by PUSH and POP)
+    fn get_stack_pair_by_index(&self, index: u8, mode: IndexMode) -> u16 {
+        match index {
+            3 => self.get_af(),
+            _ => self.get_register_pair_by_index(index, mode),
+        }
+    }
+
+    fn set_stack_pair_by_index(&mut self, index: u8, mode: IndexMode, value: u16) {
+        match index {
+            3 => self.set_af(value),
+            _ => self.set_register_pair_by_index(index, mode, value),
+        }
+    }
+
+    fn nop(&mut self) {
+        // NOP does nothing, so this function is empty
+    }
+
+    fn get_af(&self) -> u16 {
+        u16::from(self.a) << 8 | u16::from(self.f)
+    }
+
+    fn get_bc(&self) -> u16 {
+        (self.b as u16) << 8 | self.c as u16
+    }
+
+    fn get_de(&self) -> u16 {
+        (self.d as u16) << 8 | self.e as u16
+    }
+
+    fn get_hl(&self) -> u16 {
+        (self.h as u16) << 8 | self.l as u16
+    }
+
+    fn set_af(&mut self, value: u16) {
+        self.a = (value >> 8) as u8;
+        self.f = (value & 0xFF) as u8;
+    }
+
+    fn set_bc(&mut self, value: u16) {
+        self.b = (value >> 8) as u8;
+        self.c = (value & 0xFF) as u8;
+    }
+
+    fn set_de(&mut self, value: u16) {
+        self.d = (value >> 8) as u8;
+        self.e = (value & 0xFF) as u8;
+    }
+
+    fn set_hl(&mut self, value: u16) {
+        self.h = (value >> 8) as u8;
+        self.l = (value & 0xFF) as u8;
+    }
+
+    fn ld_a_bc(&mut self) {
+        let address = self.get_bc();
+        self.a = self.read_byte(address);
+        self.wz = address.wrapping_add(1);
+    }
+
+    fn ld_a_de(&mut self) {
+        let address = self.get_de();
+        self.a = self.read_byte(address);
+        self.wz = address.wrapping_add(1);
+    }
+
+    fn ld_de_a(&mut self) {
+        let address = self.get_de();
+        self.write_byte(address, self.a);
+        self.wz = (self.a as u16) << 8 | (address.wrapping_add(1) & 0xFF);
+    }
+
+    // Block transfer, search and I/O helpers
+    fn block_transfer(&mut self, step: i16) {
+        let src = self.get_hl();
+        let dest = self.get_de();
+        let value = self.read_byte(src);
+        self.write_byte(dest, value);
+
+        self.set_hl(src.wrapping_add(step as u16));
+        self.set_de(dest.wrapping_add(step as u16));
+        self.set_bc(self.get_bc().wrapping_sub(1));
+
+        let n = value.wrapping_add(self.a);
+        self.set_flag(Flag::H, false);
+        self.set_flag(Flag::P, self.get_bc() != 0);
+        self.set_flag(Flag::N, false);
+        self.set_flag(Flag::X, n & 0x08 != 0);
+        self.set_flag(Flag::Y, n & 0x02 != 0);
+    }
+
+    fn block_compare(&mut self, step: i16) {
+        let hl = self.get_hl();
+        let value = self.read_byte(hl);
+        let result = self.a.wrapping_sub(value);
+
+        self.set_hl(hl.wrapping_add(step as u16));
+        self.set_bc(self.get_bc().wrapping_sub(1));
+
+        let half_carry = (self.a & 0x0F) < (value & 0x0F);
+        let n = result.wrapping_sub(half_carry as u8);
+        self.set_flag(Flag::S, result & 0x80 != 0);
+        self.set_flag(Flag::Z, result == 0);
+        self.set_flag(Flag::H, half_carry);
+        self.set_flag(Flag::P, self.get_bc() != 0);
+        self.set_flag(Flag::N, true);
+        self.set_flag(Flag::X, n & 0x08 != 0);
+        self.set_flag(Flag::Y, n & 0x02 != 0);
+
+        self.wz = self.wz.wrapping_add(step as u16);
+    }
+
+    fn block_in(&mut self, step: i16) {
+        let hl = self.get_hl();
+        let value = self.io_read(self.get_bc());
+        self.write_byte(hl, value);
+
+        self.wz = self.get_bc().wrapping_add(step as u16);
+        self.set_hl(hl.wrapping_add(step as u16));
+        self.b = self.b.wrapping_sub(1);
+
+        let k = value as u16 + self.c.wrapping_add(step as u8) as u16;
+        self.set_block_io_flags(value, k);
+    }
+
+    fn block_out(&mut self, step: i16) {
+        let hl = self.get_hl();
+        let value = self.read_byte(hl);
+        self.b = self.b.wrapping_sub(1);
+        self.io_write(self.get_bc(), value);
+
+        self.wz = self.get_bc().wrapping_add(step as u16);
+        self.set_hl(hl.wrapping_add(step as u16));
+
+        let k = value as u16 + self.l as u16;
+        self.set_block_io_flags(value, k);
+    }
+
+    // Stack operations
+    fn push(&mut self, value: u16) {
+        self.sp = self.sp.wrapping_sub(2);
+        self.write_word(self.sp, value);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let value = self.read_word(self.sp);
+        self.sp = self.sp.wrapping_add(2);
+        value
+    }
+
+    // CALL and RET
+    fn call(&mut self, address: u16) {
+        // The PC already points past the operands, so it is the return address
+        self.push(self.pc);
+        self.pc = address;
+        self.wz = address;
+    }
+
+    fn ret(&mut self) {
+        self.pc = self.pop();
+        self.wz = self.pc;
+    }
+
+    fn rst(&mut self, address: u16) {
+        self.push(self.pc);
+        self.pc = address;
+        self.wz = address;
+    }
+
+    pub fn dump(&self, dump_memory: bool) {
+        println!("CPU State:");
+        println!("A: {:02X} F: {:02X}", self.a, self.f);
+        println!("B: {:02X} C: {:02X}", self.b, self.c);
+        println!("D: {:02X} E: {:02X}", self.d, self.e);
+        println!("H: {:02X} L: {:02X}", self.h, self.l);
+
+        println!("Flags:");
+        self.dump_flags();
+
+        println!("Alternate Registers:");
+        println!("A': {:02X} F': {:02X}", self.a_alt, self.f_alt);
+        println!("B': {:02X} C': {:02X}", self.b_alt, self.c_alt);
+        println!("D': {:02X} E': {:02X}", self.d_alt, self.e_alt);
+        println!("H': {:02X} L': {:02X}", self.h_alt, self.l_alt);
+
+        println!("16-bit Registers:");
+        println!("SP: {:04X}", self.sp);
+        println!("PC: {:04X}", self.pc);
+        println!("IX: {:04X}", self.ix);
+        println!("IY: {:04X}", self.iy);
+        println!("WZ: {:04X}", self.wz);
+        println!("I: {:02X} R: {:02X}", self.i, self.r);
+
+        println!("Interrupts:");
+        println!("IFF1: {} IFF2: {}", self.iff1, self.iff2);
+        println!("IM: {}", self.im);
+        println!("Interrupt Line: {}", self.interrupt_line.is_asserted());
+        println!("NMI Request: {}", self.nmi_request);
+
+        println!("Halted: {}", self.halted);
+
+        if dump_memory {
+            println!("Memory Dump:");
+            for address in (0x0000..0x10000).step_by(16) {
+                print!("{:04X}: ", address);
+                for offset in 0..16 {
+                    print!("{:02X} ", self.bus.peek((address + offset) as u16));
+                }
+                println!();
+            }
+        }
+    }
+
+    pub fn dump_flags(&self) {
+        fn debug_flag(value: bool) -> &'static str {
+            if value {
+                "1"
+            } else {
+                "0"
+            }
+        }
+
+        println!("Flags:");
+        println!("S (Sign):       {}", debug_flag(self.get_flag(Flag::S)));
+        println!("Z (Zero):       {}", debug_flag(self.get_flag(Flag::Z)));
+        println!("Y (Bit 5):      {}", debug_flag(self.get_flag(Flag::Y)));
+        println!("H (Half Carry): {}", debug_flag(self.get_flag(Flag::H)));
+        println!("X (Bit 3):      {}", debug_flag(self.get_flag(Flag::X)));
+        println!("P (Parity):     {}", debug_flag(self.get_flag(Flag::P)));
+        println!("N (Add/Sub):    {}", debug_flag(self.get_flag(Flag::N)));
+        println!("C (Carry):      {}", debug_flag(self.get_flag(Flag::C)));
+    }
+}
+
+fn overflow_sub(a: u8, b: u8, result: u8) -> bool {
+    ((a ^ b) & (a ^ result)) & 0x80 != 0
+}
+
+fn parity(value: u8) -> bool {
+    value.count_ones() & 1 == 0
+}
+
+fn cb_t_states(opcode: u8) -> u32 {
+    match (opcode & 0x07, opcode) {
+        (0x06, 0x40..=0x7F) => 12, // BIT b, (HL)
+        (0x06, _) => 15,           // (HL) read-modify-write
+        _ => 8,
+    }
+}
+
+// T-states of unprefixed instructions, conditional ones list the cost when the
+// condition is false. Prefixes are 0 as their tables include the prefix fetch.
+#[rustfmt::skip]
+const MAIN_T_STATES: [u8; 256] = [
+    4, 10,  7,  6,  4,  4,  7,  4,  4, 11,  7,  6,  4,  4,  7,  4, // 0x00
+    8, 10,  7,  6,  4,  4,  7,  4, 12, 11,  7,  6,  4,  4,  7,  4, // 0x10
+    7, 10, 16,  6,  4,  4,  7,  4,  7, 11, 16,  6,  4,  4,  7,  4, // 0x20
+    7, 10, 13,  6, 11, 11, 10,  4,  7, 11, 13,  6,  4,  4,  7,  4, // 0x30
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 0x40
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 0x50
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 0x60
+    7,  7,  7,  7,  7,  7,  4,  7,  4,  4,  4,  4,  4,  4,  7,  4, // 0x70
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 0x80
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 0x90
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 0xA0
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 0xB0
+    5, 10, 10, 10, 10, 11,  7, 11,  5, 10, 10,  0, 10, 17,  7, 11, // 0xC0
+    5, 10, 10, 11, 10, 11,  7, 11,  5,  4, 10, 11, 10,  0,  7, 11, // 0xD0
+    5, 10, 10, 19, 10, 11,  7, 11,  5,  4, 10,  4, 10,  0,  7, 11, // 0xE0
+    5, 10, 10,  4, 10, 11,  7, 11,  5,  6, 10,  4, 10,  0,  7, 11, // 0xF0
+];
+
+// T-states of ED prefixed instructions, including the prefix. Block
+// instructions list the cost of the last (non repeating) iteration.
+#[rustfmt::skip]
+const ED_T_STATES: [u8; 256] = [
+    8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8, // 0x00
+    8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8, // 0x10
+    8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8, // 0x20
+    8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8, // 0x30
+   12, 12, 15, 20,  8, 14,  8,  9, 12, 12, 15, 20,  8, 14,  8,  9, // 0x40
+   12, 12, 15, 20,  8, 14,  8,  9, 12, 12, 15, 20,  8, 14,  8,  9, // 0x50
+   12, 12, 15, 20,  8, 14,  8, 18, 12, 12, 15, 20,  8, 14,  8, 18, // 0x60
+   12, 12, 15, 20,  8, 14,  8,  8, 12, 12, 15, 20,  8, 14,  8,  8, // 0x70
+    8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8, // 0x80
+    8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8, // 0x90
+   16, 16, 16, 16,  8,  8,  8,  8, 16, 16, 16, 16,  8,  8,  8,  8, // 0xA0
+   16, 16, 16, 16,  8,  8,  8,  8, 16, 16, 16, 16,  8,  8,  8,  8, // 0xB0
+    8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8, // 0xC0
+    8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8, // 0xD0
+    8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8, // 0xE0
+    8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8, // 0xF0
+];
+
+// T-states of DD/FD prefixed instructions, including the prefix. Opcodes that
+// don't involve HL cost the prefix plus the unprefixed instruction. DDCB/FDCB
+// is 0, as it has its own timing.
+#[rustfmt::skip]
+const INDEX_T_STATES: [u8; 256] = [
+    8, 14, 11, 10,  8,  8, 11,  8,  8, 15, 11, 10,  8,  8, 11,  8, // 0x00
+   12, 14, 11, 10,  8,  8, 11,  8, 16, 15, 11, 10,  8,  8, 11,  8, // 0x10
+   11, 14, 20, 10,  8,  8, 11,  8, 11, 15, 20, 10,  8,  8, 11,  8, // 0x20
+   11, 14, 17, 10, 23, 23, 19,  8, 11, 15, 17, 10,  8,  8, 11,  8, // 0x30
+    8,  8,  8,  8,  8,  8, 19,  8,  8,  8,  8,  8,  8,  8, 19,  8, // 0x40
+    8,  8,  8,  8,  8,  8, 19,  8,  8,  8,  8,  8,  8,  8, 19,  8, // 0x50
+    8,  8,  8,  8,  8,  8, 19,  8,  8,  8,  8,  8,  8,  8, 19,  8, // 0x60
+   19, 19, 19, 19, 19, 19,  8, 19,  8,  8,  8,  8,  8,  8, 19,  8, // 0x70
+    8,  8,  8,  8,  8,  8, 19,  8,  8,  8,  8,  8,  8,  8, 19,  8, // 0x80
+    8,  8,  8,  8,  8,  8, 19,  8,  8,  8,  8,  8,  8,  8, 19,  8, // 0x90
+    8,  8,  8,  8,  8,  8, 19,  8,  8,  8,  8,  8,  8,  8, 19,  8, // 0xA0
+    8,  8,  8,  8,  8,  8, 19,  8,  8,  8,  8,  8,  8,  8, 19,  8, // 0xB0
+    9, 14, 14, 14, 14, 15, 11, 15,  9, 14, 14,  0, 14, 21, 11, 15, // 0xC0
+    9, 14, 14, 15, 14, 15, 11, 15,  9,  8, 14, 15, 14,  4, 11, 15, // 0xD0
+    9, 14, 14, 23, 14, 15, 11, 15,  9,  8, 14,  8, 14,  4, 11, 15, // 0xE0
+    9, 14, 14,  8, 14, 15, 11, 15,  9, 10, 14,  8, 14,  4, 11, 15, // 0xF0
+];
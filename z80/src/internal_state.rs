@@ -11,6 +11,14 @@ pub struct InternalState {
     pub e: u8,
     pub h: u8,
     pub l: u8,
+    pub i: u8,
+    pub r: u8,
+
+    // Shadow registers
+    pub af_alt: u16,
+    pub bc_alt: u16,
+    pub de_alt: u16,
+    pub hl_alt: u16,
 
     // 16-bit registers
     pub sp: u16,
@@ -25,24 +33,20 @@ pub struct InternalState {
 impl fmt::Display for InternalState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let flags = format!(
-            "S: {} Z: {} H: {} P/V: {} N: {} C: {}",
+            "S: {} Z: {} Y: {} H: {} X: {} P/V: {} N: {} C: {}",
             if self.f & 0b1000_0000 != 0 { "1" } else { "0" },
             if self.f & 0b0100_0000 != 0 { "1" } else { "0" },
+            if self.f & 0b0010_0000 != 0 { "1" } else { "0" },
             if self.f & 0b0001_0000 != 0 { "1" } else { "0" },
+            if self.f & 0b0000_1000 != 0 { "1" } else { "0" },
             if self.f & 0b0000_0100 != 0 { "1" } else { "0" },
             if self.f & 0b0000_0010 != 0 { "1" } else { "0" },
             if self.f & 0b0000_0001 != 0 { "1" } else { "0" },
         );
-        // FIXME apparently the F3 and F5 registers are accounted for on the openMSX, we're skipping it for now
-        // write!(
-        //     f,
-        //     "#{:04X} - A: #{:02X} B: #{:02X} C: #{:02X} D: #{:02X} E: #{:02X} F: #{:02X} H: #{:02X} L: #{:02X} - {}",
-        //     self.pc, self.a, self.b, self.c, self.d, self.e, self.f, self.h, self.l, flags
-        // )
         write!(
             f,
-            "#{:04X} #{:02X} - A: #{:02X} B: #{:02X} C: #{:02X} D: #{:02X} E: #{:02X} H: #{:02X} L: #{:02X} - HL: #{:04X}(#{:02X}) SP: #{:04X} - {}",
-            self.pc, self.opcode, self.a, self.b, self.c, self.d, self.e, self.h, self.l, self.hl, self.hl_contents, self.sp, flags
+            "#{:04X} #{:02X} - A: #{:02X} F: #{:02X} B: #{:02X} C: #{:02X} D: #{:02X} E: #{:02X} H: #{:02X} L: #{:02X} I: #{:02X} R: #{:02X} - HL: #{:04X}(#{:02X}) SP: #{:04X} - AF': #{:04X} BC': #{:04X} DE': #{:04X} HL': #{:04X} - {}",
+            self.pc, self.opcode, self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l, self.i, self.r, self.hl, self.hl_contents, self.sp, self.af_alt, self.bc_alt, self.de_alt, self.hl_alt, flags
         )
     }
 }
@@ -0,0 +1,10 @@
+//! A Zilog Z80 emulator, independent of the machine it runs in. Memory, I/O
+//! and interrupt acknowledge cycles go through the [`Bus`] trait.
+
+mod bus;
+mod cpu;
+mod internal_state;
+
+pub use bus::Bus;
+pub use cpu::{Flag, InterruptLine, Z80};
+pub use internal_state::InternalState;
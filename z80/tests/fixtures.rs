@@ -0,0 +1,150 @@
+//! Runs the programs in `fixtures/z80` until HALT on a flat RAM bus and checks
+//! the final state against the `expected.toml` next to each `main.bin`.
+//!
+//! The binaries are checked in, rebuild them with `make` from the repository
+//! root after changing a `main.asm`.
+
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use serde::Deserialize;
+use z80::{Bus, Z80};
+
+// Upper bound on executed instructions, a fixture that never halts fails
+// instead of hanging the test run
+const MAX_INSTRUCTIONS: usize = 100_000;
+
+#[derive(Deserialize)]
+struct Expected {
+    #[serde(default)]
+    registers: BTreeMap<String, u16>,
+    #[serde(default)]
+    memory: BTreeMap<String, u8>,
+}
+
+struct Ram {
+    data: Vec<u8>,
+}
+
+impl Bus for Ram {
+    fn read(&mut self, address: u16) -> u8 {
+        self.data[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.data[address as usize] = value;
+    }
+
+    fn input(&mut self, _port: u16) -> u8 {
+        0xFF
+    }
+
+    fn output(&mut self, _port: u16, _value: u8) {}
+
+    fn peek(&self, address: u16) -> u8 {
+        self.data[address as usize]
+    }
+}
+
+fn get_register_by_name(cpu: &Z80<Ram>, name: &str) -> u16 {
+    let pair = |high: u8, low: u8| (high as u16) << 8 | low as u16;
+
+    match name {
+        "a" => cpu.a as u16,
+        "f" => cpu.f as u16,
+        "b" => cpu.b as u16,
+        "c" => cpu.c as u16,
+        "d" => cpu.d as u16,
+        "e" => cpu.e as u16,
+        "h" => cpu.h as u16,
+        "l" => cpu.l as u16,
+        "af" => pair(cpu.a, cpu.f),
+        "bc" => pair(cpu.b, cpu.c),
+        "de" => pair(cpu.d, cpu.e),
+        "hl" => pair(cpu.h, cpu.l),
+        "ix" => cpu.ix,
+        "iy" => cpu.iy,
+        "sp" => cpu.sp,
+        "pc" => cpu.pc,
+        _ => panic!("Unknown register {}", name),
+    }
+}
+
+fn parse_address(address: &str) -> u16 {
+    let digits = address.trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).unwrap_or_else(|_| panic!("Invalid address {}", address))
+}
+
+fn run_fixture(name: &str) {
+    let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../fixtures/z80")
+        .join(name);
+
+    let program = fs::read(directory.join("main.bin")).unwrap();
+    assert!(!program.is_empty(), "{}/main.bin is empty", name);
+
+    let expected = fs::read_to_string(directory.join("expected.toml")).unwrap();
+    let expected: Expected = toml::from_str(&expected)
+        .unwrap_or_else(|err| panic!("Failed to parse {}/expected.toml: {}", name, err));
+
+    let mut ram = Ram {
+        data: vec![0; 0x10000],
+    };
+    ram.data[..program.len()].copy_from_slice(&program);
+
+    let mut cpu = Z80::new(ram);
+    cpu.pc = 0x0000;
+
+    let mut instructions = 0;
+    while !cpu.halted {
+        assert!(
+            instructions < MAX_INSTRUCTIONS,
+            "{} did not halt after {} instructions",
+            name,
+            MAX_INSTRUCTIONS
+        );
+        cpu.execute_cycle();
+        instructions += 1;
+    }
+
+    let mut errors = Vec::new();
+    for (register, &value) in &expected.registers {
+        let actual = get_register_by_name(&cpu, register);
+        if actual != value {
+            errors.push(format!(
+                "{}: got {:04X}, expected {:04X}",
+                register, actual, value
+            ));
+        }
+    }
+    for (address, &value) in &expected.memory {
+        let actual = cpu.bus.peek(parse_address(address));
+        if actual != value {
+            errors.push(format!(
+                "({}): got {:02X}, expected {:02X}",
+                address, actual, value
+            ));
+        }
+    }
+
+    assert!(errors.is_empty(), "{}: {}", name, errors.join(", "));
+}
+
+#[test]
+fn simple() {
+    run_fixture("simple");
+}
+
+#[test]
+fn stack() {
+    run_fixture("stack");
+}
+
+#[test]
+fn test_call() {
+    run_fixture("test_call");
+}
+
+#[test]
+fn jumps_and_bit_manipulation() {
+    run_fixture("jumps_and_bit_manipulation");
+}
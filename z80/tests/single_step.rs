@@ -0,0 +1,237 @@
+//! Runs the per-opcode test vectors from https://github.com/SingleStepTests/z80
+//! against the CPU on a flat RAM bus. The vectors are not part of the repo,
+//! point `Z80_SINGLE_STEP_TESTS` to their `v1` directory and run:
+//!
+//!     Z80_SINGLE_STEP_TESTS=/path/to/z80/v1 cargo test -p z80 -- --ignored
+//!
+//! Each case sets the registers and RAM, executes one instruction and compares
+//! registers, RAM, port writes and the instruction length in T-states. `ei`
+//! and `p` are not modelled and the cycle-by-cycle bus activity is only used
+//! for its length.
+
+use std::{collections::VecDeque, env, fs, path::PathBuf};
+
+use serde::Deserialize;
+use z80::{Bus, Z80};
+
+// How many failing cases are printed in full before only counting them
+const MAX_REPORTED_FAILURES: usize = 50;
+
+#[derive(Deserialize)]
+struct TestCase {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    cycles: Vec<serde_json::Value>,
+    #[serde(default)]
+    ports: Vec<(u16, u8, String)>,
+}
+
+#[derive(Deserialize)]
+struct State {
+    pc: u16,
+    sp: u16,
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+    h: u8,
+    l: u8,
+    i: u8,
+    r: u8,
+    wz: u16,
+    q: u8,
+    ix: u16,
+    iy: u16,
+    af_: u16,
+    bc_: u16,
+    de_: u16,
+    hl_: u16,
+    im: u8,
+    iff1: u8,
+    iff2: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+struct TestBus {
+    ram: Vec<u8>,
+    inputs: VecDeque<u8>,
+    outputs: Vec<(u16, u8)>,
+}
+
+impl Bus for TestBus {
+    fn read(&mut self, address: u16) -> u8 {
+        self.ram[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.ram[address as usize] = value;
+    }
+
+    fn input(&mut self, _port: u16) -> u8 {
+        self.inputs.pop_front().unwrap_or(0xFF)
+    }
+
+    fn output(&mut self, port: u16, value: u8) {
+        self.outputs.push((port, value));
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.ram[address as usize]
+    }
+}
+
+fn setup(test: &TestCase) -> Z80<TestBus> {
+    let state = &test.initial;
+
+    let mut bus = TestBus {
+        ram: vec![0; 0x10000],
+        inputs: VecDeque::new(),
+        outputs: Vec::new(),
+    };
+    for &(address, value) in &state.ram {
+        bus.ram[address as usize] = value;
+    }
+    for (_, value, direction) in &test.ports {
+        if direction == "r" {
+            bus.inputs.push_back(*value);
+        }
+    }
+
+    let mut cpu = Z80::new(bus);
+    cpu.pc = state.pc;
+    cpu.sp = state.sp;
+    cpu.a = state.a;
+    cpu.b = state.b;
+    cpu.c = state.c;
+    cpu.d = state.d;
+    cpu.e = state.e;
+    cpu.f = state.f;
+    cpu.h = state.h;
+    cpu.l = state.l;
+    cpu.i = state.i;
+    cpu.r = state.r;
+    cpu.wz = state.wz;
+    cpu.q = state.q;
+    cpu.ix = state.ix;
+    cpu.iy = state.iy;
+    [cpu.a_alt, cpu.f_alt] = state.af_.to_be_bytes();
+    [cpu.b_alt, cpu.c_alt] = state.bc_.to_be_bytes();
+    [cpu.d_alt, cpu.e_alt] = state.de_.to_be_bytes();
+    [cpu.h_alt, cpu.l_alt] = state.hl_.to_be_bytes();
+    cpu.im = state.im;
+    cpu.iff1 = state.iff1 != 0;
+    cpu.iff2 = state.iff2 != 0;
+    cpu
+}
+
+// Returns a description of every difference from the expected final state
+fn compare(test: &TestCase, cpu: &Z80<TestBus>, t_states: u32) -> Vec<String> {
+    let expected = &test.expected;
+    let mut errors = Vec::new();
+
+    let mut check = |name: &str, actual: u16, expected: u16| {
+        if actual != expected {
+            errors.push(format!(
+                "{}: got {:04X}, expected {:04X}",
+                name, actual, expected
+            ));
+        }
+    };
+
+    check("PC", cpu.pc, expected.pc);
+    check("SP", cpu.sp, expected.sp);
+    check("A", cpu.a as u16, expected.a as u16);
+    check("F", cpu.f as u16, expected.f as u16);
+    check("B", cpu.b as u16, expected.b as u16);
+    check("C", cpu.c as u16, expected.c as u16);
+    check("D", cpu.d as u16, expected.d as u16);
+    check("E", cpu.e as u16, expected.e as u16);
+    check("H", cpu.h as u16, expected.h as u16);
+    check("L", cpu.l as u16, expected.l as u16);
+    check("I", cpu.i as u16, expected.i as u16);
+    check("R", cpu.r as u16, expected.r as u16);
+    check("WZ", cpu.wz, expected.wz);
+    check("Q", cpu.q as u16, expected.q as u16);
+    check("IX", cpu.ix, expected.ix);
+    check("IY", cpu.iy, expected.iy);
+    let pair = |high: u8, low: u8| (high as u16) << 8 | low as u16;
+    check("AF'", pair(cpu.a_alt, cpu.f_alt), expected.af_);
+    check("BC'", pair(cpu.b_alt, cpu.c_alt), expected.bc_);
+    check("DE'", pair(cpu.d_alt, cpu.e_alt), expected.de_);
+    check("HL'", pair(cpu.h_alt, cpu.l_alt), expected.hl_);
+    check("IM", cpu.im as u16, expected.im as u16);
+    check("IFF1", cpu.iff1 as u16, expected.iff1 as u16);
+    check("IFF2", cpu.iff2 as u16, expected.iff2 as u16);
+    check("T-states", t_states as u16, test.cycles.len() as u16);
+
+    for &(address, value) in &expected.ram {
+        check(
+            &format!("({:04X})", address),
+            cpu.bus.peek(address) as u16,
+            value as u16,
+        );
+    }
+
+    let writes: Vec<(u16, u8)> = test
+        .ports
+        .iter()
+        .filter(|(_, _, direction)| direction == "w")
+        .map(|&(port, value, _)| (port, value))
+        .collect();
+    if cpu.bus.outputs != writes {
+        errors.push(format!(
+            "port writes: got {:04X?}, expected {:04X?}",
+            cpu.bus.outputs, writes
+        ));
+    }
+
+    errors
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests vectors, see the module documentation"]
+fn single_step_tests() {
+    let directory = env::var("Z80_SINGLE_STEP_TESTS")
+        .map(PathBuf::from)
+        .expect("Z80_SINGLE_STEP_TESTS must point to the SingleStepTests/z80 v1 directory");
+
+    let mut files: Vec<PathBuf> = fs::read_dir(&directory)
+        .expect("Failed to read the test directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty(), "No tests found in {:?}", directory);
+
+    let mut passed = 0;
+    let mut failed = 0;
+
+    for file in files {
+        let data = fs::read_to_string(&file).unwrap();
+        let tests: Vec<TestCase> = serde_json::from_str(&data)
+            .unwrap_or_else(|err| panic!("Failed to parse {:?}: {}", file, err));
+
+        for test in tests {
+            let mut cpu = setup(&test);
+            let t_states = cpu.execute_cycle();
+
+            let errors = compare(&test, &cpu, t_states);
+            if errors.is_empty() {
+                passed += 1;
+                continue;
+            }
+
+            failed += 1;
+            if failed <= MAX_REPORTED_FAILURES {
+                println!("{}: {}", test.name, errors.join(", "));
+            }
+        }
+    }
+
+    println!("{} passed, {} failed", passed, failed);
+    assert_eq!(failed, 0, "{} of {} cases failed", failed, passed + failed);
+}